@@ -13,6 +13,8 @@ LUAMOD_API int luaopen_assert(lua_State *L) {
 #else
   const auto code = R"EOC(pluto_use "0.6.0"
 
+local getinfo = ...
+
 local module = {}
 
 local function deepCompare(t1, t2)
@@ -116,7 +118,7 @@ local class AssertionError
     local caller
 
     for i = 2, 255 do
-      caller = debug.getinfo(i)
+      caller = getinfo(i)
       if caller and not tostring(caller.short_src):contains("[") then
         break
       end
@@ -312,7 +314,11 @@ module.AssertionError = AssertionError
 
 return module)EOC";
   luaL_loadbuffer(L, code, strlen(code), "pluto:assert");
-  lua_call(L, 0, 1);
+  /* 'debug.getinfo', which is available even without the debug library */
+  luaopen_debug(L);
+  lua_getfield(L, -1, "getinfo");
+  lua_remove(L, -2);
+  lua_call(L, 1, 1);
   return 1;
 #endif
 }
@@ -26,7 +26,7 @@
 #include "lua.h"
 #include "lprefix.h"
 #include "lauxlib.h"
-#ifdef PLUTO_MEMORY_LIMIT
+#if defined(PLUTO_MEMORY_LIMIT) || defined(PLUTO_ERROR_STYLE_PER_STATE)
 #include "lstate.h"
 #endif
 
@@ -1110,7 +1110,11 @@ static void *l_alloc (void *ud, void *ptr, size_t osize, size_t nsize) {
 #ifdef PLUTO_MEMORY_LIMIT
     if (ud  /* state has finished opening? */
       && (!ptr || nsize > osize)  /* new allocation or increasing existing allocation? */
+#ifdef PLUTO_MEMORY_LIMIT_PER_STATE
+      && gettotalbytes(reinterpret_cast<global_State*>(ud)) >= reinterpret_cast<global_State*>(ud)->memory_limit  /* limit reached? */
+#else
       && gettotalbytes(reinterpret_cast<global_State*>(ud)) >= PLUTO_MEMORY_LIMIT  /* limit reached? */
+#endif
       ) {
       return NULL;
     }
@@ -1204,6 +1208,32 @@ LUALIB_API lua_State *luaL_newstate (void) {
 }
 
 
+#ifdef PLUTO_MEMORY_LIMIT_PER_STATE
+/*
+** Changes the memory limit of a state created with luaL_newstate.
+*/
+LUALIB_API void pluto_set_memory_limit (lua_State *L, size_t limit) {
+  G(L)->memory_limit = limit;
+}
+#endif
+
+
+#ifdef PLUTO_ERROR_STYLE_PER_STATE
+/*
+** Changes the style of the error messages of a state (and its threads),
+** a combination of PLUTO_ERRSTYLE_* flags.
+*/
+LUALIB_API void pluto_set_error_style (lua_State *L, int style) {
+  G(L)->error_style = style;
+}
+
+
+LUALIB_API int pluto_get_error_style (lua_State *L) {
+  return G(L)->error_style;
+}
+#endif
+
+
 LUALIB_API void luaL_checkversion_ (lua_State *L, lua_Number ver, size_t sz) {
   lua_Number v = lua_version(L);
   if (sz != LUAL_NUMSIZES)  /* check numeric types */
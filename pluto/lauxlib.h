@@ -134,6 +134,13 @@ LUALIB_API int (luaL_loadbufferx) (lua_State *L, const char *buff, size_t sz,
 LUALIB_API int (luaL_loadstring) (lua_State *L, const char *s);
 
 LUALIB_API lua_State *(luaL_newstate) (void);
+#ifdef PLUTO_MEMORY_LIMIT_PER_STATE
+LUALIB_API void (pluto_set_memory_limit) (lua_State *L, size_t limit);
+#endif
+#ifdef PLUTO_ERROR_STYLE_PER_STATE
+LUALIB_API void (pluto_set_error_style) (lua_State *L, int style);
+LUALIB_API int (pluto_get_error_style) (lua_State *L);
+#endif
 
 LUALIB_API lua_Integer (luaL_len) (lua_State *L, int idx);
 
@@ -3,6 +3,7 @@
 #include <string>
 
 #include "llex.h"
+#include "lstate.h"
 #include "vendor/Soup/soup/string.hpp"
 
 namespace Pluto {
@@ -12,6 +13,25 @@ namespace Pluto {
 		size_t src_len = 0; // The size of the source line itself.
 		size_t line_len = 0; // The buffer size needed to align a bar (|) or note (+).
 
+		// Whether code snippets are excluded at runtime, see pluto_set_error_style.
+		[[nodiscard]] bool isShort() const noexcept {
+#ifdef PLUTO_ERROR_STYLE_PER_STATE
+			return G(ls->L)->error_style & PLUTO_ERRSTYLE_SHORT;
+#else
+			return false;
+#endif
+		}
+
+		// Whether ANSI color codes are included at runtime, see pluto_set_error_style.
+		// Otherwise, the color macros are empty unless PLUTO_USE_COLORED_OUTPUT is defined.
+		[[nodiscard]] bool isColored() const noexcept {
+#ifdef PLUTO_ERROR_STYLE_PER_STATE
+			return G(ls->L)->error_style & PLUTO_ERRSTYLE_COLOR;
+#else
+			return true;
+#endif
+		}
+
 	public:
 		std::string content{};
 
@@ -28,8 +48,15 @@ namespace Pluto {
 			return *this;
 		}
 
+		// Appends one of the color macros, if the error style includes colors.
+		ErrorMessage& addColor(const char* color) {
+			if (isColored()) this->content.append(color);
+			return *this;
+		}
+
 		ErrorMessage& addSrcLine(int line) {
-#ifndef PLUTO_SHORT_ERRORS
+#if !defined(PLUTO_SHORT_ERRORS) || defined(PLUTO_ERROR_STYLE_PER_STATE)
+			if (isShort()) return *this;
 			const auto line_string = this->ls->getLineString(line);
 			const auto init_len = this->content.length();
 			this->content.append("\n    ");
@@ -43,40 +70,45 @@ namespace Pluto {
 		}
 
 		ErrorMessage& addGenericHere(const char* msg) { // TO-DO: Add '^^^' strings for specific keywords. Not accurate with a simple string search.
-#ifndef PLUTO_SHORT_ERRORS
+#if !defined(PLUTO_SHORT_ERRORS) || defined(PLUTO_ERROR_STYLE_PER_STATE)
+			if (isShort()) return *this;
 			if (*msg == '\0') {
 				return addGenericHere();
 			}
 			this->content.push_back('\n');
 			this->content.append(this->line_len, ' ');
 			this->content.append("| ");
-			this->content.append(HBLU);
+			addColor(HBLU);
 			this->content.append(this->src_len, '^');
 			this->content.append(" here: ");
 			this->content.append(msg);
-			this->content.append(RESET);
+			addColor(RESET);
 #endif
 			return *this;
 		}
 
 		ErrorMessage& addGenericHere() {
-#ifndef PLUTO_SHORT_ERRORS
+#if !defined(PLUTO_SHORT_ERRORS) || defined(PLUTO_ERROR_STYLE_PER_STATE)
+			if (isShort()) return *this;
 			this->content.push_back('\n');
 			this->content.append(this->line_len, ' ');
 			this->content.append("| ");
-			this->content.append(HBLU);
+			addColor(HBLU);
 			this->content.append(this->src_len, '^');
 			this->content.append(" here");
-			this->content.append(RESET);
+			addColor(RESET);
 #endif
 			return *this;
 		}
 
 		ErrorMessage& addNote(std::string&& msg) {
-#ifndef PLUTO_SHORT_ERRORS
+#if !defined(PLUTO_SHORT_ERRORS) || defined(PLUTO_ERROR_STYLE_PER_STATE)
+			if (isShort()) return *this;
 			this->content.push_back('\n');
 			this->content.append(this->line_len, ' ');
-			this->content.append(HCYN "+ note: " RESET);
+			addColor(HCYN);
+			this->content.append("+ note: ");
+			addColor(RESET);
 
 			if (msg.find("\n") != std::string::npos) { // Multi-line note?
 				std::vector<std::string> lines = soup::string::explode(msg, '\n');
@@ -104,7 +136,7 @@ namespace Pluto {
 
 		// Pushes the string to the stack for luaD_throw to conveniently pick up.
 		void finalize() {
-			this->content.append(RESET);
+			addColor(RESET);
 			lua_pushlstring(ls->L, this->content.data(), this->content.size());
 		}
 
@@ -97,8 +97,13 @@ static int http_request (lua_State *L) {
   luaL_error(L, "disallowed by content moderation policy");
 #endif
 #ifdef PLUTO_HTTP_REQUEST_HOOK
-  if (!PLUTO_HTTP_REQUEST_HOOK(L, uri.c_str()))
-    luaL_error(L, "disallowed by content moderation policy");
+  {
+    int top = lua_gettop(L);
+    if (!PLUTO_HTTP_REQUEST_HOOK(L, uri.c_str()))
+      luaL_error(L, "disallowed by content moderation policy");
+    if (lua_gettop(L) > top)  /* hook served a response? */
+      return lua_gettop(L) - top;
+  }
 #endif
 
 #if SOUP_WASM
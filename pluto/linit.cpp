@@ -35,6 +35,10 @@
 #include "lualib.h"
 #include "lauxlib.h"
 
+#ifdef PLUTO_PRELUDE
+#include "pluto_prelude.h"
+#endif
+
 
 /*
 ** these libs are loaded by lua.c and are readily available to any Lua
@@ -76,10 +80,17 @@ LUALIB_API void luaL_openlibs (lua_State *L) {
   }
   lua_pop(L, 1);
 
-#ifndef PLUTO_DONT_LOAD_ANY_STANDARD_LIBRARY_CODE_WRITTEN_IN_PLUTO
+#if defined(PLUTO_PRELUDE)
+  /* startup code provided by the embedder, terminated by a NUL byte */
+  if (luaL_loadbuffer(L, (const char *)pluto_prelude, sizeof(pluto_prelude) - 1, pluto_prelude_name) != LUA_OK)
+    lua_error(L);
+  lua_call(L, 0, 0);
+#elif !defined(PLUTO_DONT_LOAD_ANY_STANDARD_LIBRARY_CODE_WRITTEN_IN_PLUTO)
   const auto startup_code = R"EOC(
 pluto_use "0.6.0"
 
+local getinfo = ...
+
 class exception
     __name = "pluto:exception"
 
@@ -87,13 +98,13 @@ class exception
         local caller
         local i = 2
         while true do
-            caller = debug.getinfo(i)
+            caller = getinfo(i)
             if caller == nil then
                 error("exception instances must be created with 'pluto_new'", 0)
             end
             ++i
             if caller.name == "Pluto_operator_new" then
-                caller = debug.getinfo(i)
+                caller = getinfo(i)
                 break
             end
         end
@@ -111,7 +122,11 @@ function instanceof(a, b)
 end
 )EOC";
   luaL_loadbuffer(L, startup_code, strlen(startup_code), "Pluto Standard Library");
-  lua_call(L, 0, 0);
+  /* 'debug.getinfo', which is available even without the debug library */
+  luaopen_debug(L);
+  lua_getfield(L, -1, "getinfo");
+  lua_remove(L, -2);
+  lua_call(L, 1, 0);
 #endif
 }
 
@@ -173,8 +173,9 @@ const char* luaX_reserved2str (int token) {
 
 static l_noret lexerror (LexState *ls, const char *msg, const Token& t) {
   msg = luaG_addinfo(ls->L, msg, ls->source, ls->getLineNumber());
-  auto err = new Pluto::ErrorMessage{ ls, HRED "syntax error: " BWHT };
-  err->addMsg(msg);
+  auto err = new Pluto::ErrorMessage{ ls };
+  err->addColor(HRED).addMsg("syntax error: ").addColor(BWHT)
+     .addMsg(msg);
   if (t.token) {
     err->addMsg(" near ")
        .addMsg(luaX_token2str(ls, t))
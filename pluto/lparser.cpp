@@ -112,8 +112,9 @@ static void expr (LexState *ls, expdesc *v, TypeHint *prop = nullptr, int flags
 */
 static l_noret throwerr (LexState *ls, const char *err, const char *here, int line, const char *note = nullptr) {
   err = luaG_addinfo(ls->L, err, ls->source, line);
-  auto msg = new Pluto::ErrorMessage{ ls, HRED "syntax error: " BWHT }; // We'll only throw syntax errors if 'throwerr' is called
-  msg->addMsg(err);
+  auto msg = new Pluto::ErrorMessage{ ls }; // We'll only throw syntax errors if 'throwerr' is called
+  msg->addColor(HRED).addMsg("syntax error: ").addColor(BWHT)
+     .addMsg(err);
   if (ls->t.token == TK_EOS && strstr(err, "near '<eof>'") == nullptr) {  /* for 'incomplete' in REPL */
     msg->addMsg(" near ")
        .addMsg(luaX_token2str(ls, ls->t));
@@ -136,8 +137,9 @@ static l_noret throwerr (LexState *ls, const char *err, const char *here, const
 // No note.
 static void throw_warn (LexState *ls, const char *err, const char *here, int line, WarningType warningType) {
   if (ls->shouldEmitWarning(line, warningType)) {
-    auto msg = new Pluto::ErrorMessage{ ls, luaG_addinfo(ls->L, YEL "warning: " BWHT, ls->source, line) };
-    msg->addMsg(err)
+    auto msg = new Pluto::ErrorMessage{ ls, luaG_addinfo(ls->L, "", ls->source, line) };
+    msg->addColor(YEL).addMsg("warning: ").addColor(BWHT)
+      .addMsg(err)
       .addMsg(" [")
       .addMsg(ls->getWarningConfig().getWarningName(warningType))
       .addMsg("]")
@@ -157,8 +159,9 @@ static void throw_warn (LexState *ls, const char *err, const char *here, int lin
 // Note.
 static void throw_warn(LexState* ls, const char* err, const char* here, const char* note, int line, WarningType warningType) {
   if (ls->shouldEmitWarning(line, warningType)) {
-    auto msg = new Pluto::ErrorMessage{ ls, luaG_addinfo(ls->L, YEL "warning: " BWHT, ls->source, line) };
-    msg->addMsg(err)
+    auto msg = new Pluto::ErrorMessage{ ls, luaG_addinfo(ls->L, "", ls->source, line) };
+    msg->addColor(YEL).addMsg("warning: ").addColor(BWHT)
+      .addMsg(err)
       .addMsg(" [")
       .addMsg(ls->getWarningConfig().getWarningName(warningType))
       .addMsg("]")
@@ -357,8 +360,9 @@ static void check_match (LexState *ls, int what, int who, int where, const char*
         throwerr(ls, msg, "this was the last statement.", ls->getLineNumberOfLastNonEmptyLine());
       }
       else {
-        auto err = new Pluto::ErrorMessage{ ls, RED "syntax error: " BWHT };
-        err->addMsg(luaX_token2str(ls, what))
+        auto err = new Pluto::ErrorMessage{ ls };
+        err->addColor(RED).addMsg("syntax error: ").addColor(BWHT)
+          .addMsg(luaX_token2str(ls, what))
           .addMsg(" expected (to close ")
           .addMsg(luaX_token2str(ls, who))
           .addMsg(" on line ")
@@ -473,6 +473,18 @@ LUA_API lua_State *lua_newstate (lua_Alloc f, void *ud) {
   g->scheduler = nullptr;
 #ifdef PLUTO_ETL_ENABLE
   g->deadline = std::chrono::duration_cast<std::chrono::nanoseconds>(std::chrono::steady_clock::now().time_since_epoch()).count() + PLUTO_ETL_NANOS;
+#endif
+#ifdef PLUTO_MEMORY_LIMIT_PER_STATE
+  g->memory_limit = PLUTO_MEMORY_LIMIT;
+#endif
+#ifdef PLUTO_ERROR_STYLE_PER_STATE
+  g->error_style = 0;
+#ifdef PLUTO_SHORT_ERRORS
+  g->error_style |= PLUTO_ERRSTYLE_SHORT;
+#endif
+#ifdef PLUTO_USE_COLORED_OUTPUT
+  g->error_style |= PLUTO_ERRSTYLE_COLOR;
+#endif
 #endif
   if (luaD_rawrunprotected(L, f_luaopen, NULL) != LUA_OK) {
     /* memory allocation error: free partial state */
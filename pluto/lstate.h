@@ -335,6 +335,12 @@ typedef struct global_State {
 #ifdef PLUTO_ETL_ENABLE
   std::time_t deadline;  /* internal use only; do not use this in your own code. */
 #endif
+#ifdef PLUTO_MEMORY_LIMIT_PER_STATE
+  size_t memory_limit;  /* internal use only; do not use this in your own code. */
+#endif
+#ifdef PLUTO_ERROR_STYLE_PER_STATE
+  int error_style;  /* internal use only; do not use this in your own code. */
+#endif
 #ifndef PLUTO_NO_DEFAULT_TABLE_METATABLE
   TValue table_mt;  /* internal use only; do not use this in your own code. */
 #endif
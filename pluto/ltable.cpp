@@ -652,11 +652,11 @@ void luaH_initmetatable (lua_State *L, Table *t) {
     /* assign to stack value */
     sethvalue(L, s2v(L->top.p - 1), table_mt);
     /* set __index */
-    L->ci->top.p++;
+    L->ci->top.p += 2;  /* key and value */
     lua_pushliteral(L, "__index");
     lua_getglobal(L, "table");
     lua_settable(L, -3);
-    L->ci->top.p--;
+    L->ci->top.p -= 2;
   }
   else {
     sethvalue(L, s2v(L->top.p - 1), hvalue(&G(L)->table_mt));
@@ -819,6 +819,14 @@
 // If defined, Pluto will exclude code snippets from error messages to make them shorter.
 //#define PLUTO_SHORT_ERRORS
 
+// If defined, the style of error messages can be changed for each state at runtime via pluto_set_error_style.
+// PLUTO_SHORT_ERRORS and PLUTO_USE_COLORED_OUTPUT then set the initial style of new states.
+//#define PLUTO_ERROR_STYLE_PER_STATE
+
+// Flags of the error style of a state.
+#define PLUTO_ERRSTYLE_SHORT 1 // Exclude code snippets.
+#define PLUTO_ERRSTYLE_COLOR 2 // Use ANSI color codes.
+
 // If defined, Pluto won't assume that source files are UTF-8 encoded and restrict valid symbol names.
 //#define PLUTO_NO_UTF8
 
@@ -975,6 +983,14 @@
 
 //#define PLUTO_MEMORY_LIMIT 64'000'000 /* 64 MB (megabytes, not mebibytes!) */
 
+// If defined, the memory limit of each state can be changed at runtime via pluto_set_memory_limit.
+// PLUTO_MEMORY_LIMIT is then the initial limit of new states (unlimited if not defined).
+//#define PLUTO_MEMORY_LIMIT_PER_STATE
+
+#if defined(PLUTO_MEMORY_LIMIT_PER_STATE) && !defined(PLUTO_MEMORY_LIMIT)
+#define PLUTO_MEMORY_LIMIT (~(size_t)0)
+#endif
+
 /*
 ** {====================================================================
 ** Pluto Configuration: VM Dump
@@ -993,11 +1009,15 @@
 */
 
 // Opcodes listed in this structure are a blacklist. They not be printed when VM dumping.
+#ifndef vmDumpIgnore
 #define vmDumpIgnore
+#endif
 
 
 // Opcodes listed in this structure are a whitelist. They are only printed when VM dumping.
+#ifndef vmDumpAllow
 #define vmDumpAllow
+#endif
 
 // If defined, Pluto will use vmDumpAllow instead of vmDumpIgnore.
 //#define PLUTO_VMDUMP_WHITELIST
@@ -1063,7 +1083,8 @@
 //#define PLUTO_DISABLE_HTTP_COMPLETELY
 
 // If defined, the provided function will be called as bool(lua_State* L, const char* url).
-// If it returns false, a Lua error is raised.
+// If it returns false, a Lua error is raised. If it returns true after pushing values, 'http.request' returns them
+// instead of sending the request.
 // Note that the 'socket' library can still be used to the same effect (with more effort).
 //#define PLUTO_HTTP_REQUEST_HOOK ContmodOnHttpRequest
 
@@ -1100,7 +1121,7 @@
 ** =====================================================================}
 */
 
-#ifdef PLUTO_USE_COLORED_OUTPUT // Don't need to write any 'ifdef' macro logic inside of Pluto::ErrorMessage.
+#if defined(PLUTO_USE_COLORED_OUTPUT) || defined(PLUTO_ERROR_STYLE_PER_STATE) // Don't need to write any 'ifdef' macro logic inside of Pluto::ErrorMessage.
 #define ESC "\x1B"
 
 #define BLK ESC "[0;30m"
@@ -5,6 +5,8 @@
 ** See Copyright Notice in lua.h
 */
 
+#include <initializer_list>
+
 #include "lua.h"
 #include "lauxlib.h" // Pluto::Preloaded
 
@@ -63,26 +65,60 @@ namespace Pluto {
   extern const PreloadedLibrary preloaded_ffi;
   extern const PreloadedLibrary preloaded_canvas;
 
-  inline const PreloadedLibrary* const all_preloaded[] = {
+  // Libraries can be left out of the preloaded set by defining PLUTO_NO_<NAME>LIB.
+  // This is an initializer_list rather than an array so that it may end up empty.
+  inline const std::initializer_list<const PreloadedLibrary*> all_preloaded = {
+#ifndef PLUTO_NO_CRYPTOLIB
     &preloaded_crypto,
+#endif
+#ifndef PLUTO_NO_JSONLIB
     &preloaded_json,
+#endif
+#ifndef PLUTO_NO_BASE32LIB
     &preloaded_base32,
+#endif
+#ifndef PLUTO_NO_BASE64LIB
     &preloaded_base64,
+#endif
+#ifndef PLUTO_NO_ASSERTLIB
     &preloaded_assert,
+#endif
+#ifndef PLUTO_NO_VECTOR3LIB
     &preloaded_vector3,
+#endif
+#ifndef PLUTO_NO_URLLIB
     &preloaded_url,
+#endif
+#ifndef PLUTO_NO_STARLIB
     &preloaded_star,
+#endif
+#ifndef PLUTO_NO_CATLIB
     &preloaded_cat,
+#endif
+#ifndef PLUTO_NO_HTTPLIB
     &preloaded_http,
+#endif
+#ifndef PLUTO_NO_SCHEDULERLIB
     &preloaded_scheduler,
-#ifndef __EMSCRIPTEN__
+#endif
+#if !defined(__EMSCRIPTEN__) && !defined(PLUTO_NO_SOCKETLIB)
     &preloaded_socket,
 #endif
+#ifndef PLUTO_NO_BIGINTLIB
     &preloaded_bigint,
+#endif
+#ifndef PLUTO_NO_XMLLIB
     &preloaded_xml,
+#endif
+#ifndef PLUTO_NO_REGEXLIB
     &preloaded_regex,
+#endif
+#ifndef PLUTO_NO_FFILIB
     &preloaded_ffi,
+#endif
+#ifndef PLUTO_NO_CANVASLIB
     &preloaded_canvas,
+#endif
   };
 
   extern const ConstexprLibrary constexpr_io;
@@ -1310,11 +1310,12 @@ static const std::vector<OpCode> allowOps = { vmDumpAllow };
   bool ignore = true; \
   OpCode opcode = GET_OPCODE(i); \
   std::string tmp; \
+  if (PLUTO_VMDUMP_COND(L)) \
   for (size_t idx = 0; idx < allowOps.size(); idx++) \
     if (allowOps.at(idx) == opcode) { tmp = opnames[opcode]; padUntilGoal(tmp, 11); ignore = false; break; }
 #else
 #define vmDumpInit() \
-  bool ignore = false; \
+  bool ignore = !(PLUTO_VMDUMP_COND(L)); \
   OpCode opcode = GET_OPCODE(i); \
   std::string tmp; \
   for (size_t idx = 0; idx < ignoreOps.size(); idx++) \
@@ -0,0 +1,309 @@
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+use crate::{Build, Error};
+
+/// Checks Pluto scripts for syntax errors and parser warnings at build time.
+///
+/// Scripts are parsed with a `plutoc` built for the host, with all parser warnings enabled
+/// (see [`Build::parser_warnings`]) and code snippets in the diagnostics.
+#[derive(Clone)]
+pub struct ScriptCheck {
+    out_dir: Option<PathBuf>,
+    host: Option<String>,
+    // Configuration of the parser (defaults to `Build::new`)
+    config: Option<Build>,
+    patterns: Vec<String>,
+}
+
+/// The diagnostics reported by [`ScriptCheck::try_check`].
+pub struct CheckReport {
+    watched: BTreeSet<PathBuf>,
+    scripts: Vec<PathBuf>,
+    warnings: Vec<String>,
+    errors: Vec<String>,
+}
+
+/// Checks the scripts matching `patterns`, panicking if any of them has a syntax error.
+///
+/// Errors and warnings are reported as `cargo:warning=` lines.
+/// See [`ScriptCheck::pattern`] for the pattern syntax.
+pub fn check_scripts<P: AsRef<str>>(patterns: &[P]) {
+    let mut check = ScriptCheck::new();
+    for pattern in patterns {
+        check.pattern(pattern.as_ref());
+    }
+    check.check();
+}
+
+impl ScriptCheck {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> ScriptCheck {
+        ScriptCheck {
+            out_dir: env::var_os("OUT_DIR").map(|s| PathBuf::from(s).join("pluto-check")),
+            host: env::var("HOST").ok(),
+            config: None,
+            patterns: Vec::new(),
+        }
+    }
+
+    pub fn out_dir<P: AsRef<Path>>(&mut self, path: P) -> &mut ScriptCheck {
+        self.out_dir = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn host(&mut self, host: &str) -> &mut ScriptCheck {
+        self.host = Some(host.to_string());
+        self
+    }
+
+    /// Sets the configuration of the Pluto library that will run the scripts.
+    pub fn config(&mut self, build: &Build) -> &mut ScriptCheck {
+        self.config = Some(build.clone());
+        self
+    }
+
+    /// Adds the scripts matching `pattern`, relative to the current directory.
+    ///
+    /// `*` and `?` match within a path component and `**` matches any number of directories,
+    /// e.g. `scripts/**/*.pluto`. `**` doesn't descend into symlinked directories.
+    pub fn pattern(&mut self, pattern: &str) -> &mut ScriptCheck {
+        self.patterns.push(pattern.to_string());
+        self
+    }
+
+    /// Checks the scripts and prints the diagnostics, panicking if there are any errors.
+    pub fn check(&mut self) -> CheckReport {
+        let report = match self.try_check() {
+            Ok(report) => report,
+            Err(err) => panic!("{err}"),
+        };
+        report.print_cargo_metadata();
+        if !report.errors.is_empty() {
+            panic!("{} Pluto script(s) failed to compile", report.errors.len());
+        }
+        report
+    }
+
+    /// Checks the scripts, returning an error only if they couldn't be checked.
+    ///
+    /// Syntax errors in the scripts are part of the returned [`CheckReport`].
+    pub fn try_check(&mut self) -> Result<CheckReport, Error> {
+        let host = &self.host.as_ref().ok_or(Error::MissingEnv("HOST"))?[..];
+        let out_dir = self.out_dir.as_ref().ok_or(Error::MissingEnv("OUT_DIR"))?;
+
+        let mut roots = BTreeSet::new();
+        let mut scripts = BTreeSet::new();
+        for pattern in &self.patterns {
+            let (root, files) = glob(pattern)?;
+            roots.extend(root);
+            scripts.extend(files);
+        }
+
+        let mut config = self.config.clone().unwrap_or_else(Build::new);
+        config.parser_warnings(true).short_errors(false);
+        let plutoc = config.build_host_plutoc(host, &out_dir.join("plutoc"))?;
+
+        let scripts: Vec<PathBuf> = scripts.into_iter().collect();
+        let (mut warnings, mut errors) = (Vec::new(), Vec::new());
+        let prefix = format!("{}: ", plutoc.display());
+        for script in &scripts {
+            let output = Command::new(&plutoc)
+                .arg("-p")
+                .arg("--")
+                .arg(script)
+                .output()
+                .map_err(|err| Error::io(&plutoc, err))?;
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let errors_len = errors.len();
+            for (line, note) in diagnostics(&stderr) {
+                // Errors are prefixed with the program name, warnings start with the script
+                let (list, line) = match line.strip_prefix(&prefix) {
+                    Some(error) => {
+                        let error = error.strip_prefix("syntax error: ").unwrap_or(error);
+                        (&mut errors, error)
+                    }
+                    None => (&mut warnings, line),
+                };
+                list.push(match note {
+                    Some(note) => format!("{line} ({note})"),
+                    None => line.to_string(),
+                });
+            }
+            if !output.status.success() && errors.len() == errors_len {
+                errors.push(format!("{}: plutoc {}", script.display(), output.status));
+            }
+        }
+
+        // Watch the pattern roots to pick up new scripts
+        let watched = roots.into_iter().chain(scripts.iter().cloned()).collect();
+        Ok(CheckReport {
+            watched,
+            scripts,
+            warnings,
+            errors,
+        })
+    }
+}
+
+impl CheckReport {
+    /// The checked scripts, sorted by path.
+    pub fn scripts(&self) -> &[PathBuf] {
+        &self.scripts
+    }
+
+    /// Parser warnings, formatted as `file:line: warning: message [name] (note)`.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Syntax errors, formatted as `file:line: message (note)`.
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    pub fn print_cargo_metadata(&self) {
+        for path in &self.watched {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+        for diagnostic in self.errors.iter().chain(&self.warnings) {
+            println!("cargo:warning={diagnostic}");
+        }
+    }
+}
+
+/// Splits `plutoc` output into diagnostic lines and the `here:` note that follows them.
+///
+/// Each diagnostic starts on an unindented line and is followed by indented source excerpts.
+fn diagnostics(output: &str) -> Vec<(&str, Option<&str>)> {
+    let mut diagnostics: Vec<(&str, Option<&str>)> = Vec::new();
+    for line in output.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            if !line.is_empty() {
+                diagnostics.push((line, None));
+            }
+        } else if let Some((_, note)) = diagnostics.last_mut() {
+            if let Some((_, here)) = line.split_once("here: ") {
+                note.get_or_insert(here.trim());
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Returns the files matching `pattern`, sorted by path, and the path the pattern is
+/// rooted at (the longest leading part without wildcards), unless it is the current directory.
+fn glob(pattern: &str) -> Result<(Option<PathBuf>, Vec<PathBuf>), Error> {
+    let mut root = PathBuf::new();
+    let mut components = Path::new(pattern).components().peekable();
+    while let Some(component) = components.peek() {
+        match component {
+            Component::Normal(name) if is_wildcard(&name.to_string_lossy()) => break,
+            _ => root.push(components.next().unwrap()),
+        }
+    }
+    let rest: Vec<String> =
+        (components.map(|c| c.as_os_str().to_string_lossy().into_owned())).collect();
+
+    let mut files = BTreeSet::new();
+    if root.as_os_str().is_empty() {
+        glob_dir(Path::new("."), &rest, &mut files)?;
+        return Ok((None, files.into_iter().collect()));
+    }
+    glob_dir(&root, &rest, &mut files)?;
+    Ok((Some(root), files.into_iter().collect()))
+}
+
+fn glob_dir(dir: &Path, pattern: &[String], files: &mut BTreeSet<PathBuf>) -> Result<(), Error> {
+    let Some((component, rest)) = pattern.split_first() else {
+        if dir.is_file() {
+            files.insert(dir.strip_prefix(".").unwrap_or(dir).to_path_buf());
+        }
+        return Ok(());
+    };
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    if component == "**" {
+        glob_dir(dir, rest, files)?;
+    }
+    let entries = fs::read_dir(dir).map_err(|err| Error::io(dir, err))?;
+    for entry in entries {
+        let entry = entry.map_err(|err| Error::io(dir, err))?;
+        let path = entry.path();
+        if component == "**" {
+            // Symlinks aren't followed here, as they may point back to a parent directory
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                glob_dir(&path, pattern, files)?;
+            }
+        } else if wildcard_match(component, &entry.file_name().to_string_lossy()) {
+            glob_dir(&path, rest, files)?;
+        }
+    }
+    Ok(())
+}
+
+fn is_wildcard(s: &str) -> bool {
+    s.contains(['*', '?'])
+}
+
+/// Matches `name` against a pattern where `*` matches any characters and `?` any one.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), name.chars().collect());
+    // Position to resume from after the last `*`, in the pattern and in the name
+    let mut star = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_glob_symlink_loop() {
+        let dir = env::temp_dir().join(format!("pluto-src-glob-{}", std::process::id()));
+        fs::create_dir_all(dir.join("scripts/lib")).unwrap();
+        fs::write(dir.join("scripts/main.pluto"), "").unwrap();
+        fs::write(dir.join("scripts/lib/util.pluto"), "").unwrap();
+        std::os::unix::fs::symlink("..", dir.join("scripts/lib/parent")).unwrap();
+        std::os::unix::fs::symlink(".", dir.join("scripts/self")).unwrap();
+
+        let result = glob(&format!("{}/**/*.pluto", dir.display()));
+        fs::remove_dir_all(&dir).unwrap();
+        let (root, files) = result.unwrap();
+        assert_eq!(root, Some(dir.clone()));
+        assert_eq!(
+            files,
+            [
+                dir.join("scripts/lib/util.pluto"),
+                dir.join("scripts/main.pluto"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,789 @@
+//! Raw bindings to the Pluto C API (`lua.h`, `lauxlib.h` and `lualib.h`).
+//!
+//! The declarations match the headers shipped with this crate and are checked against them
+//! by the test suite. Functions use the `C-unwind` ABI, as Lua errors are raised with C++
+//! exceptions (or `longjmp`, see [`Build::use_longjmp`](crate::Build::use_longjmp)).
+//!
+//! Some functions only exist with a matching build configuration:
+//! - `luaopen_*` of the Pluto libraries left out by
+//!   [`Build::preloaded_libs`](crate::Build::preloaded_libs)
+//! - [`pluto_set_memory_limit`], enabled by
+//!   [`Build::per_state_memory_limit`](crate::Build::per_state_memory_limit)
+//! - [`pluto_set_error_style`] and [`pluto_get_error_style`], enabled by
+//!   [`Build::per_state_error_style`](crate::Build::per_state_error_style)
+//! - [`lua_setcachelen`] and the table freezing functions, left out by
+//!   [`Build::disable_length_cache`](crate::Build::disable_length_cache) and
+//!   [`Build::disable_table_freezing`](crate::Build::disable_table_freezing)
+//!
+//! Pluto additions declared with `PLUTO_API` instead of `LUA_API` are not available: they
+//! have C++ linkage, so their symbol names are mangled differently by each compiler, and
+//! all but `pluto_warning` take or return `std::string`. Neither is `lua_pushvfstring`,
+//! which takes a `va_list`.
+
+#![allow(non_camel_case_types, non_snake_case, clippy::missing_safety_doc)]
+
+use std::ffi::{c_char, c_double, c_int, c_uchar, c_uint, c_ushort, c_void};
+use std::marker::{PhantomData, PhantomPinned};
+use std::{mem, ptr};
+
+// Version
+pub const LUA_VERSION_NUM: c_int = 504;
+pub const LUA_VERSION_RELEASE_NUM: c_int = LUA_VERSION_NUM * 100 + 7;
+pub const LUA_VERSION: &str = "Lua 5.4";
+pub const LUA_RELEASE: &str = "Lua 5.4.7";
+pub const PLUTO_VERSION: &str = "Pluto 0.10.4";
+
+/// Mark for precompiled code (`<esc>Lua`).
+pub const LUA_SIGNATURE: &[u8] = b"\x1bLua";
+
+/// Option for multiple returns in `lua_pcall` and `lua_call`.
+pub const LUA_MULTRET: c_int = -1;
+
+/// Maximum size of the Lua stack, which the pseudo-indices depend on.
+///
+/// This is the default on targets with a 32-bit `int`. The module of a build
+/// ([`Artifacts::ffi_module`](crate::Artifacts::ffi_module)) has the size set with
+/// [`Build::set_max_stack_size`](crate::Build::set_max_stack_size) instead.
+pub const LUAI_MAXSTACK: c_int = 1_000_000;
+
+pub const LUA_REGISTRYINDEX: c_int = lua_registryindex(LUAI_MAXSTACK);
+
+/// Returns the registry pseudo-index for a build with the given maximum stack size.
+pub const fn lua_registryindex(max_stack_size: c_int) -> c_int {
+    -max_stack_size - 1000
+}
+
+pub const fn lua_upvalueindex(i: c_int) -> c_int {
+    LUA_REGISTRYINDEX - i
+}
+
+// Thread status
+pub const LUA_OK: c_int = 0;
+pub const LUA_YIELD: c_int = 1;
+pub const LUA_ERRRUN: c_int = 2;
+pub const LUA_ERRSYNTAX: c_int = 3;
+pub const LUA_ERRMEM: c_int = 4;
+pub const LUA_ERRERR: c_int = 5;
+pub const LUA_ERRFILE: c_int = LUA_ERRERR + 1;
+
+/// A Lua state (opaque).
+#[repr(C)]
+pub struct lua_State {
+    _data: [u8; 0],
+    _marker: PhantomData<(*mut u8, PhantomPinned)>,
+}
+
+// Basic types
+pub const LUA_TNONE: c_int = -1;
+pub const LUA_TNIL: c_int = 0;
+pub const LUA_TBOOLEAN: c_int = 1;
+pub const LUA_TLIGHTUSERDATA: c_int = 2;
+pub const LUA_TNUMBER: c_int = 3;
+pub const LUA_TSTRING: c_int = 4;
+pub const LUA_TTABLE: c_int = 5;
+pub const LUA_TFUNCTION: c_int = 6;
+pub const LUA_TUSERDATA: c_int = 7;
+pub const LUA_TTHREAD: c_int = 8;
+pub const LUA_NUMTYPES: c_int = 9;
+
+/// Minimum Lua stack available to a C function.
+pub const LUA_MINSTACK: c_int = 20;
+
+// Predefined values in the registry
+pub const LUA_RIDX_MAINTHREAD: lua_Integer = 1;
+pub const LUA_RIDX_GLOBALS: lua_Integer = 2;
+pub const LUA_RIDX_LAST: lua_Integer = LUA_RIDX_GLOBALS;
+
+pub type lua_Number = c_double;
+pub type lua_Integer = i64;
+pub type lua_Unsigned = u64;
+pub type lua_KContext = isize;
+
+pub type lua_CFunction = unsafe extern "C-unwind" fn(L: *mut lua_State) -> c_int;
+pub type lua_KFunction =
+    unsafe extern "C-unwind" fn(L: *mut lua_State, status: c_int, ctx: lua_KContext) -> c_int;
+
+pub type lua_Reader = unsafe extern "C-unwind" fn(
+    L: *mut lua_State,
+    ud: *mut c_void,
+    sz: *mut usize,
+) -> *const c_char;
+pub type lua_Writer = unsafe extern "C-unwind" fn(
+    L: *mut lua_State,
+    p: *const c_void,
+    sz: usize,
+    ud: *mut c_void,
+) -> c_int;
+
+pub type lua_Alloc = unsafe extern "C-unwind" fn(
+    ud: *mut c_void,
+    ptr: *mut c_void,
+    osize: usize,
+    nsize: usize,
+) -> *mut c_void;
+
+pub type lua_WarnFunction =
+    unsafe extern "C-unwind" fn(ud: *mut c_void, msg: *const c_char, tocont: c_int);
+
+pub type lua_Hook = unsafe extern "C-unwind" fn(L: *mut lua_State, ar: *mut lua_Debug);
+
+// Arithmetic and comparison operators
+pub const LUA_OPADD: c_int = 0;
+pub const LUA_OPSUB: c_int = 1;
+pub const LUA_OPMUL: c_int = 2;
+pub const LUA_OPMOD: c_int = 3;
+pub const LUA_OPPOW: c_int = 4;
+pub const LUA_OPDIV: c_int = 5;
+pub const LUA_OPIDIV: c_int = 6;
+pub const LUA_OPBAND: c_int = 7;
+pub const LUA_OPBOR: c_int = 8;
+pub const LUA_OPBXOR: c_int = 9;
+pub const LUA_OPSHL: c_int = 10;
+pub const LUA_OPSHR: c_int = 11;
+pub const LUA_OPUNM: c_int = 12;
+pub const LUA_OPBNOT: c_int = 13;
+
+pub const LUA_OPEQ: c_int = 0;
+pub const LUA_OPLT: c_int = 1;
+pub const LUA_OPLE: c_int = 2;
+
+// Garbage-collection options
+pub const LUA_GCSTOP: c_int = 0;
+pub const LUA_GCRESTART: c_int = 1;
+pub const LUA_GCCOLLECT: c_int = 2;
+pub const LUA_GCCOUNT: c_int = 3;
+pub const LUA_GCCOUNTB: c_int = 4;
+pub const LUA_GCSTEP: c_int = 5;
+pub const LUA_GCSETPAUSE: c_int = 6;
+pub const LUA_GCSETSTEPMUL: c_int = 7;
+pub const LUA_GCISRUNNING: c_int = 9;
+pub const LUA_GCGEN: c_int = 10;
+pub const LUA_GCINC: c_int = 11;
+
+// Debug event codes and masks
+pub const LUA_HOOKCALL: c_int = 0;
+pub const LUA_HOOKRET: c_int = 1;
+pub const LUA_HOOKLINE: c_int = 2;
+pub const LUA_HOOKCOUNT: c_int = 3;
+pub const LUA_HOOKTAILCALL: c_int = 4;
+
+pub const LUA_MASKCALL: c_int = 1 << LUA_HOOKCALL;
+pub const LUA_MASKRET: c_int = 1 << LUA_HOOKRET;
+pub const LUA_MASKLINE: c_int = 1 << LUA_HOOKLINE;
+pub const LUA_MASKCOUNT: c_int = 1 << LUA_HOOKCOUNT;
+
+/// Maximum size for the description of the source of a function in debug information.
+pub const LUA_IDSIZE: usize = 60;
+
+/// Size of the raw memory area associated with a Lua state.
+pub const LUA_EXTRASPACE: usize = mem::size_of::<*const c_void>();
+
+#[repr(C)]
+pub struct lua_Debug {
+    pub event: c_int,
+    pub name: *const c_char,
+    pub namewhat: *const c_char,
+    pub what: *const c_char,
+    pub source: *const c_char,
+    pub srclen: usize,
+    pub currentline: c_int,
+    pub linedefined: c_int,
+    pub lastlinedefined: c_int,
+    pub nups: c_uchar,
+    pub nparams: c_uchar,
+    pub isvararg: c_char,
+    pub istailcall: c_char,
+    pub ftransfer: c_ushort,
+    pub ntransfer: c_ushort,
+    pub short_src: [c_char; LUA_IDSIZE],
+    // Private part
+    i_ci: *mut c_void,
+}
+
+extern "C-unwind" {
+    // State manipulation
+    pub fn lua_newstate(f: lua_Alloc, ud: *mut c_void) -> *mut lua_State;
+    pub fn lua_close(L: *mut lua_State);
+    pub fn lua_newthread(L: *mut lua_State) -> *mut lua_State;
+    pub fn lua_closethread(L: *mut lua_State, from: *mut lua_State) -> c_int;
+    pub fn lua_resetthread(L: *mut lua_State) -> c_int;
+
+    pub fn lua_atpanic(L: *mut lua_State, panicf: lua_CFunction) -> lua_CFunction;
+
+    pub fn lua_version(L: *mut lua_State) -> lua_Number;
+
+    // Basic stack manipulation
+    pub fn lua_absindex(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_gettop(L: *mut lua_State) -> c_int;
+    pub fn lua_settop(L: *mut lua_State, idx: c_int);
+    pub fn lua_pushvalue(L: *mut lua_State, idx: c_int);
+    pub fn lua_rotate(L: *mut lua_State, idx: c_int, n: c_int);
+    pub fn lua_copy(L: *mut lua_State, fromidx: c_int, toidx: c_int);
+    pub fn lua_checkstack(L: *mut lua_State, n: c_int) -> c_int;
+
+    pub fn lua_xmove(from: *mut lua_State, to: *mut lua_State, n: c_int);
+
+    // Access functions (stack -> C)
+    pub fn lua_isnumber(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_isstring(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_iscfunction(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_isinteger(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_istrue(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_isuserdata(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_type(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_typename(L: *mut lua_State, tp: c_int) -> *const c_char;
+
+    pub fn lua_tonumberx(L: *mut lua_State, idx: c_int, isnum: *mut c_int) -> lua_Number;
+    pub fn lua_tointegerx(L: *mut lua_State, idx: c_int, isnum: *mut c_int) -> lua_Integer;
+    pub fn lua_toboolean(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_tolstring(L: *mut lua_State, idx: c_int, len: *mut usize) -> *const c_char;
+    pub fn lua_rawlen(L: *mut lua_State, idx: c_int) -> lua_Unsigned;
+    pub fn lua_tocfunction(L: *mut lua_State, idx: c_int) -> Option<lua_CFunction>;
+    pub fn lua_touserdata(L: *mut lua_State, idx: c_int) -> *mut c_void;
+    pub fn lua_tothread(L: *mut lua_State, idx: c_int) -> *mut lua_State;
+    pub fn lua_topointer(L: *mut lua_State, idx: c_int) -> *const c_void;
+
+    // Comparison and arithmetic functions
+    pub fn lua_arith(L: *mut lua_State, op: c_int);
+    pub fn lua_rawequal(L: *mut lua_State, idx1: c_int, idx2: c_int) -> c_int;
+    pub fn lua_compare(L: *mut lua_State, idx1: c_int, idx2: c_int, op: c_int) -> c_int;
+
+    // Push functions (C -> stack)
+    pub fn lua_pushnil(L: *mut lua_State);
+    pub fn lua_pushnumber(L: *mut lua_State, n: lua_Number);
+    pub fn lua_pushinteger(L: *mut lua_State, n: lua_Integer);
+    pub fn lua_pushlstring(L: *mut lua_State, s: *const c_char, len: usize) -> *const c_char;
+    pub fn lua_pushstring(L: *mut lua_State, s: *const c_char) -> *const c_char;
+    pub fn lua_pushfstring(L: *mut lua_State, fmt: *const c_char, ...) -> *const c_char;
+    pub fn lua_pushcclosure(L: *mut lua_State, f: lua_CFunction, n: c_int);
+    pub fn lua_pushboolean(L: *mut lua_State, b: c_int);
+    pub fn lua_pushlightuserdata(L: *mut lua_State, p: *mut c_void);
+    pub fn lua_pushthread(L: *mut lua_State) -> c_int;
+
+    // Get functions (Lua -> stack)
+    pub fn lua_getglobal(L: *mut lua_State, name: *const c_char) -> c_int;
+    pub fn lua_gettable(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_getfield(L: *mut lua_State, idx: c_int, k: *const c_char) -> c_int;
+    pub fn lua_geti(L: *mut lua_State, idx: c_int, n: lua_Integer) -> c_int;
+    pub fn lua_rawget(L: *mut lua_State, idx: c_int) -> c_int;
+    pub fn lua_rawgeti(L: *mut lua_State, idx: c_int, n: lua_Integer) -> c_int;
+    pub fn lua_rawgetp(L: *mut lua_State, idx: c_int, p: *const c_void) -> c_int;
+
+    pub fn lua_createtable(L: *mut lua_State, narr: c_int, nrec: c_int);
+    pub fn lua_newuserdatauv(L: *mut lua_State, sz: usize, nuvalue: c_int) -> *mut c_void;
+    pub fn lua_getmetatable(L: *mut lua_State, objindex: c_int) -> c_int;
+    pub fn lua_getiuservalue(L: *mut lua_State, idx: c_int, n: c_int) -> c_int;
+
+    // Set functions (stack -> Lua)
+    pub fn lua_setglobal(L: *mut lua_State, name: *const c_char);
+    pub fn lua_settable(L: *mut lua_State, idx: c_int);
+    pub fn lua_setfield(L: *mut lua_State, idx: c_int, k: *const c_char);
+    pub fn lua_seti(L: *mut lua_State, idx: c_int, n: lua_Integer);
+    pub fn lua_rawset(L: *mut lua_State, idx: c_int);
+    pub fn lua_rawseti(L: *mut lua_State, idx: c_int, n: lua_Integer);
+    pub fn lua_rawsetp(L: *mut lua_State, idx: c_int, p: *const c_void);
+    pub fn lua_setmetatable(L: *mut lua_State, objindex: c_int) -> c_int;
+    pub fn lua_setiuservalue(L: *mut lua_State, idx: c_int, n: c_int) -> c_int;
+    /// Not available with [`Build::disable_length_cache`](crate::Build::disable_length_cache).
+    pub fn lua_setcachelen(L: *mut lua_State, len: lua_Unsigned, idx: c_int);
+    /// Not available with [`Build::disable_table_freezing`](crate::Build::disable_table_freezing).
+    pub fn lua_freezetable(L: *mut lua_State, idx: c_int);
+    /// Not available with [`Build::disable_table_freezing`](crate::Build::disable_table_freezing).
+    pub fn lua_istablefrozen(L: *mut lua_State, idx: c_int) -> c_int;
+    /// Not available with [`Build::disable_table_freezing`](crate::Build::disable_table_freezing).
+    pub fn lua_erriffrozen(L: *mut lua_State, idx: c_int);
+
+    // 'load' and 'call' functions (load and run Lua code)
+    pub fn lua_callk(
+        L: *mut lua_State,
+        nargs: c_int,
+        nresults: c_int,
+        ctx: lua_KContext,
+        k: Option<lua_KFunction>,
+    );
+    pub fn lua_pcallk(
+        L: *mut lua_State,
+        nargs: c_int,
+        nresults: c_int,
+        errfunc: c_int,
+        ctx: lua_KContext,
+        k: Option<lua_KFunction>,
+    ) -> c_int;
+
+    pub fn lua_load(
+        L: *mut lua_State,
+        reader: lua_Reader,
+        dt: *mut c_void,
+        chunkname: *const c_char,
+        mode: *const c_char,
+    ) -> c_int;
+
+    pub fn lua_dump(
+        L: *mut lua_State,
+        writer: lua_Writer,
+        data: *mut c_void,
+        strip: c_int,
+    ) -> c_int;
+
+    // Coroutine functions
+    pub fn lua_yieldk(
+        L: *mut lua_State,
+        nresults: c_int,
+        ctx: lua_KContext,
+        k: Option<lua_KFunction>,
+    ) -> c_int;
+    pub fn lua_resume(
+        L: *mut lua_State,
+        from: *mut lua_State,
+        narg: c_int,
+        nres: *mut c_int,
+    ) -> c_int;
+    pub fn lua_status(L: *mut lua_State) -> c_int;
+    pub fn lua_isyieldable(L: *mut lua_State) -> c_int;
+
+    // Warning-related functions
+    pub fn lua_setwarnf(L: *mut lua_State, f: Option<lua_WarnFunction>, ud: *mut c_void);
+    pub fn lua_warning(L: *mut lua_State, msg: *const c_char, tocont: c_int);
+
+    // Garbage-collection function
+    pub fn lua_gc(L: *mut lua_State, what: c_int, ...) -> c_int;
+
+    // Miscellaneous functions
+    pub fn lua_error(L: *mut lua_State) -> !;
+
+    pub fn lua_next(L: *mut lua_State, idx: c_int) -> c_int;
+
+    pub fn lua_concat(L: *mut lua_State, n: c_int);
+    pub fn lua_len(L: *mut lua_State, idx: c_int);
+
+    pub fn lua_stringtonumber(L: *mut lua_State, s: *const c_char) -> usize;
+
+    pub fn lua_getallocf(L: *mut lua_State, ud: *mut *mut c_void) -> lua_Alloc;
+    pub fn lua_setallocf(L: *mut lua_State, f: lua_Alloc, ud: *mut c_void);
+
+    pub fn lua_toclose(L: *mut lua_State, idx: c_int);
+    pub fn lua_closeslot(L: *mut lua_State, idx: c_int);
+
+    pub fn lua_insert(L: *mut lua_State, idx: c_int);
+    pub fn lua_remove(L: *mut lua_State, idx: c_int);
+    pub fn lua_replace(L: *mut lua_State, idx: c_int);
+
+    // Debug API
+    pub fn lua_getstack(L: *mut lua_State, level: c_int, ar: *mut lua_Debug) -> c_int;
+    pub fn lua_getinfo(L: *mut lua_State, what: *const c_char, ar: *mut lua_Debug) -> c_int;
+    pub fn lua_getlocal(L: *mut lua_State, ar: *const lua_Debug, n: c_int) -> *const c_char;
+    pub fn lua_setlocal(L: *mut lua_State, ar: *const lua_Debug, n: c_int) -> *const c_char;
+    pub fn lua_getupvalue(L: *mut lua_State, funcindex: c_int, n: c_int) -> *const c_char;
+    pub fn lua_setupvalue(L: *mut lua_State, funcindex: c_int, n: c_int) -> *const c_char;
+
+    pub fn lua_upvalueid(L: *mut lua_State, fidx: c_int, n: c_int) -> *mut c_void;
+    pub fn lua_upvaluejoin(L: *mut lua_State, fidx1: c_int, n1: c_int, fidx2: c_int, n2: c_int);
+
+    pub fn lua_sethook(L: *mut lua_State, func: Option<lua_Hook>, mask: c_int, count: c_int);
+    pub fn lua_gethook(L: *mut lua_State) -> Option<lua_Hook>;
+    pub fn lua_gethookmask(L: *mut lua_State) -> c_int;
+    pub fn lua_gethookcount(L: *mut lua_State) -> c_int;
+
+    pub fn lua_setcstacklimit(L: *mut lua_State, limit: c_uint) -> c_int;
+}
+
+// Macros from `lua.h`
+
+#[inline(always)]
+pub unsafe fn lua_getextraspace(L: *mut lua_State) -> *mut c_void {
+    L.cast::<u8>().sub(LUA_EXTRASPACE).cast()
+}
+
+#[inline(always)]
+pub unsafe fn lua_tonumber(L: *mut lua_State, i: c_int) -> lua_Number {
+    lua_tonumberx(L, i, ptr::null_mut())
+}
+
+#[inline(always)]
+pub unsafe fn lua_tointeger(L: *mut lua_State, i: c_int) -> lua_Integer {
+    lua_tointegerx(L, i, ptr::null_mut())
+}
+
+#[inline(always)]
+pub unsafe fn lua_pop(L: *mut lua_State, n: c_int) {
+    lua_settop(L, -n - 1)
+}
+
+#[inline(always)]
+pub unsafe fn lua_newtable(L: *mut lua_State) {
+    lua_createtable(L, 0, 0)
+}
+
+#[inline(always)]
+pub unsafe fn lua_register(L: *mut lua_State, n: *const c_char, f: lua_CFunction) {
+    lua_pushcfunction(L, f);
+    lua_setglobal(L, n)
+}
+
+#[inline(always)]
+pub unsafe fn lua_pushcfunction(L: *mut lua_State, f: lua_CFunction) {
+    lua_pushcclosure(L, f, 0)
+}
+
+#[inline(always)]
+pub unsafe fn lua_isfunction(L: *mut lua_State, n: c_int) -> bool {
+    lua_type(L, n) == LUA_TFUNCTION
+}
+
+#[inline(always)]
+pub unsafe fn lua_istable(L: *mut lua_State, n: c_int) -> bool {
+    lua_type(L, n) == LUA_TTABLE
+}
+
+#[inline(always)]
+pub unsafe fn lua_islightuserdata(L: *mut lua_State, n: c_int) -> bool {
+    lua_type(L, n) == LUA_TLIGHTUSERDATA
+}
+
+#[inline(always)]
+pub unsafe fn lua_isnil(L: *mut lua_State, n: c_int) -> bool {
+    lua_type(L, n) == LUA_TNIL
+}
+
+#[inline(always)]
+pub unsafe fn lua_isboolean(L: *mut lua_State, n: c_int) -> bool {
+    lua_type(L, n) == LUA_TBOOLEAN
+}
+
+#[inline(always)]
+pub unsafe fn lua_isthread(L: *mut lua_State, n: c_int) -> bool {
+    lua_type(L, n) == LUA_TTHREAD
+}
+
+#[inline(always)]
+pub unsafe fn lua_isnone(L: *mut lua_State, n: c_int) -> bool {
+    lua_type(L, n) == LUA_TNONE
+}
+
+#[inline(always)]
+pub unsafe fn lua_isnoneornil(L: *mut lua_State, n: c_int) -> bool {
+    lua_type(L, n) <= 0
+}
+
+#[inline(always)]
+pub unsafe fn lua_pushglobaltable(L: *mut lua_State) {
+    lua_rawgeti(L, LUA_REGISTRYINDEX, LUA_RIDX_GLOBALS);
+}
+
+#[inline(always)]
+pub unsafe fn lua_tostring(L: *mut lua_State, i: c_int) -> *const c_char {
+    lua_tolstring(L, i, ptr::null_mut())
+}
+
+#[inline(always)]
+pub unsafe fn lua_call(L: *mut lua_State, n: c_int, r: c_int) {
+    lua_callk(L, n, r, 0, None)
+}
+
+#[inline(always)]
+pub unsafe fn lua_pcall(L: *mut lua_State, n: c_int, r: c_int, f: c_int) -> c_int {
+    lua_pcallk(L, n, r, f, 0, None)
+}
+
+#[inline(always)]
+pub unsafe fn lua_yield(L: *mut lua_State, n: c_int) -> c_int {
+    lua_yieldk(L, n, 0, None)
+}
+
+#[inline(always)]
+pub unsafe fn lua_newuserdata(L: *mut lua_State, sz: usize) -> *mut c_void {
+    lua_newuserdatauv(L, sz, 1)
+}
+
+#[inline(always)]
+pub unsafe fn lua_getuservalue(L: *mut lua_State, idx: c_int) -> c_int {
+    lua_getiuservalue(L, idx, 1)
+}
+
+#[inline(always)]
+pub unsafe fn lua_setuservalue(L: *mut lua_State, idx: c_int) -> c_int {
+    lua_setiuservalue(L, idx, 1)
+}
+
+// Auxiliary library (`lauxlib.h`)
+
+/// Name of the global table.
+pub const LUA_GNAME: &str = "_G";
+
+/// Key, in the registry, for the table of loaded modules.
+pub const LUA_LOADED_TABLE: &str = "_LOADED";
+
+/// Key, in the registry, for the table of preloaded loaders.
+pub const LUA_PRELOAD_TABLE: &str = "_PRELOAD";
+
+// Predefined references
+pub const LUA_NOREF: c_int = -2;
+pub const LUA_REFNIL: c_int = -1;
+
+pub const LUAL_NUMSIZES: usize = mem::size_of::<lua_Integer>() * 16 + mem::size_of::<lua_Number>();
+
+/// Initial buffer size used by the buffer system.
+pub const LUAL_BUFFERSIZE: usize =
+    16 * mem::size_of::<*const c_void>() * mem::size_of::<lua_Number>();
+
+/// Metatable name of file handles created by the `io` library.
+pub const LUA_FILEHANDLE: &str = "FILE*";
+
+// Flags of the error style of a state (`luaconf.h`), see `pluto_set_error_style`
+pub const PLUTO_ERRSTYLE_SHORT: c_int = 1;
+pub const PLUTO_ERRSTYLE_COLOR: c_int = 2;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct luaL_Reg {
+    pub name: *const c_char,
+    pub func: Option<lua_CFunction>,
+}
+
+#[repr(C)]
+pub struct luaL_Buffer {
+    pub b: *mut c_char,
+    pub size: usize,
+    pub n: usize,
+    pub L: *mut lua_State,
+    pub init: luaL_BufferInit,
+}
+
+/// Initial storage of a [`luaL_Buffer`], aligned for any Lua value.
+#[repr(C)]
+pub union luaL_BufferInit {
+    n: lua_Number,
+    u: c_double,
+    s: *mut c_void,
+    i: lua_Integer,
+    l: std::ffi::c_long,
+    pub b: [c_char; LUAL_BUFFERSIZE],
+}
+
+/// Initial structure of a file handle userdata (the `FILE*` is opaque here).
+#[repr(C)]
+pub struct luaL_Stream {
+    pub f: *mut c_void,
+    pub closef: Option<lua_CFunction>,
+}
+
+extern "C-unwind" {
+    pub fn luaL_checkversion_(L: *mut lua_State, ver: lua_Number, sz: usize);
+
+    pub fn luaL_getmetafield(L: *mut lua_State, obj: c_int, e: *const c_char) -> c_int;
+    pub fn luaL_callmeta(L: *mut lua_State, obj: c_int, e: *const c_char) -> c_int;
+    pub fn luaL_tolstring(L: *mut lua_State, idx: c_int, len: *mut usize) -> *const c_char;
+    pub fn luaL_argerror(L: *mut lua_State, arg: c_int, extramsg: *const c_char) -> !;
+    pub fn luaL_typeerror(L: *mut lua_State, arg: c_int, tname: *const c_char) -> !;
+    pub fn luaL_checklstring(L: *mut lua_State, arg: c_int, l: *mut usize) -> *const c_char;
+    pub fn luaL_optlstring(
+        L: *mut lua_State,
+        arg: c_int,
+        def: *const c_char,
+        l: *mut usize,
+    ) -> *const c_char;
+
+    pub fn luaL_checknumber(L: *mut lua_State, arg: c_int) -> lua_Number;
+    pub fn luaL_optnumber(L: *mut lua_State, arg: c_int, def: lua_Number) -> lua_Number;
+
+    pub fn luaL_checkinteger(L: *mut lua_State, arg: c_int) -> lua_Integer;
+    pub fn luaL_optinteger(L: *mut lua_State, arg: c_int, def: lua_Integer) -> lua_Integer;
+
+    pub fn luaL_checkstack(L: *mut lua_State, sz: c_int, msg: *const c_char);
+    pub fn luaL_checktype(L: *mut lua_State, arg: c_int, t: c_int);
+    pub fn luaL_checkany(L: *mut lua_State, arg: c_int);
+
+    pub fn luaL_newmetatable(L: *mut lua_State, tname: *const c_char) -> c_int;
+    pub fn luaL_setmetatable(L: *mut lua_State, tname: *const c_char);
+    pub fn luaL_testudata(L: *mut lua_State, ud: c_int, tname: *const c_char) -> *mut c_void;
+    pub fn luaL_checkudata(L: *mut lua_State, ud: c_int, tname: *const c_char) -> *mut c_void;
+
+    pub fn luaL_where(L: *mut lua_State, lvl: c_int);
+    pub fn luaL_error(L: *mut lua_State, fmt: *const c_char, ...) -> !;
+
+    pub fn luaL_checkoption(
+        L: *mut lua_State,
+        arg: c_int,
+        def: *const c_char,
+        lst: *const *const c_char,
+    ) -> c_int;
+
+    pub fn luaL_fileresult(L: *mut lua_State, stat: c_int, fname: *const c_char) -> c_int;
+    pub fn luaL_execresult(L: *mut lua_State, stat: c_int) -> c_int;
+
+    pub fn luaL_ref(L: *mut lua_State, t: c_int) -> c_int;
+    pub fn luaL_unref(L: *mut lua_State, t: c_int, r#ref: c_int);
+
+    /// Opens a file with a UTF-8 name, returning a `FILE*`.
+    pub fn luaL_fopen(
+        filename: *const c_char,
+        filename_len: usize,
+        mode: *const c_char,
+        mode_len: usize,
+    ) -> *mut c_void;
+
+    pub fn luaL_loadfilex(L: *mut lua_State, filename: *const c_char, mode: *const c_char)
+        -> c_int;
+
+    pub fn luaL_loadbufferx(
+        L: *mut lua_State,
+        buff: *const c_char,
+        sz: usize,
+        name: *const c_char,
+        mode: *const c_char,
+    ) -> c_int;
+    pub fn luaL_loadstring(L: *mut lua_State, s: *const c_char) -> c_int;
+
+    pub fn luaL_newstate() -> *mut lua_State;
+
+    /// Only available with [`Build::per_state_memory_limit`](crate::Build::per_state_memory_limit).
+    pub fn pluto_set_memory_limit(L: *mut lua_State, limit: usize);
+    /// Only available with [`Build::per_state_error_style`](crate::Build::per_state_error_style).
+    pub fn pluto_set_error_style(L: *mut lua_State, style: c_int);
+    /// Only available with [`Build::per_state_error_style`](crate::Build::per_state_error_style).
+    pub fn pluto_get_error_style(L: *mut lua_State) -> c_int;
+
+    pub fn luaL_len(L: *mut lua_State, idx: c_int) -> lua_Integer;
+
+    pub fn luaL_addgsub(b: *mut luaL_Buffer, s: *const c_char, p: *const c_char, r: *const c_char);
+    pub fn luaL_gsub(
+        L: *mut lua_State,
+        s: *const c_char,
+        p: *const c_char,
+        r: *const c_char,
+    ) -> *const c_char;
+
+    pub fn luaL_setfuncs(L: *mut lua_State, l: *const luaL_Reg, nup: c_int);
+
+    pub fn luaL_getsubtable(L: *mut lua_State, idx: c_int, fname: *const c_char) -> c_int;
+
+    pub fn luaL_traceback(L: *mut lua_State, L1: *mut lua_State, msg: *const c_char, level: c_int);
+
+    pub fn luaL_requiref(
+        L: *mut lua_State,
+        modname: *const c_char,
+        openf: lua_CFunction,
+        glb: c_int,
+    );
+
+    // Generic buffer manipulation
+    pub fn luaL_buffinit(L: *mut lua_State, B: *mut luaL_Buffer);
+    pub fn luaL_prepbuffsize(B: *mut luaL_Buffer, sz: usize) -> *mut c_char;
+    pub fn luaL_addlstring(B: *mut luaL_Buffer, s: *const c_char, l: usize);
+    pub fn luaL_addstring(B: *mut luaL_Buffer, s: *const c_char);
+    pub fn luaL_addvalue(B: *mut luaL_Buffer);
+    pub fn luaL_pushresult(B: *mut luaL_Buffer);
+    pub fn luaL_pushresultsize(B: *mut luaL_Buffer, sz: usize);
+    pub fn luaL_buffinitsize(L: *mut lua_State, B: *mut luaL_Buffer, sz: usize) -> *mut c_char;
+}
+
+// Macros from `lauxlib.h`
+
+#[inline(always)]
+pub unsafe fn luaL_checkversion(L: *mut lua_State) {
+    luaL_checkversion_(L, LUA_VERSION_NUM as lua_Number, LUAL_NUMSIZES)
+}
+
+#[inline(always)]
+pub unsafe fn luaL_loadfile(L: *mut lua_State, f: *const c_char) -> c_int {
+    luaL_loadfilex(L, f, ptr::null())
+}
+
+#[inline(always)]
+pub unsafe fn luaL_checkstring(L: *mut lua_State, n: c_int) -> *const c_char {
+    luaL_checklstring(L, n, ptr::null_mut())
+}
+
+#[inline(always)]
+pub unsafe fn luaL_optstring(L: *mut lua_State, n: c_int, d: *const c_char) -> *const c_char {
+    luaL_optlstring(L, n, d, ptr::null_mut())
+}
+
+#[inline(always)]
+pub unsafe fn luaL_typename(L: *mut lua_State, i: c_int) -> *const c_char {
+    lua_typename(L, lua_type(L, i))
+}
+
+#[inline(always)]
+pub unsafe fn luaL_dofile(L: *mut lua_State, f: *const c_char) -> c_int {
+    match luaL_loadfile(L, f) {
+        LUA_OK => lua_pcall(L, 0, LUA_MULTRET, 0),
+        status => status,
+    }
+}
+
+#[inline(always)]
+pub unsafe fn luaL_dostring(L: *mut lua_State, s: *const c_char) -> c_int {
+    match luaL_loadstring(L, s) {
+        LUA_OK => lua_pcall(L, 0, LUA_MULTRET, 0),
+        status => status,
+    }
+}
+
+#[inline(always)]
+pub unsafe fn luaL_getmetatable(L: *mut lua_State, n: *const c_char) -> c_int {
+    lua_getfield(L, LUA_REGISTRYINDEX, n)
+}
+
+#[inline(always)]
+pub unsafe fn luaL_loadbuffer(
+    L: *mut lua_State,
+    s: *const c_char,
+    sz: usize,
+    n: *const c_char,
+) -> c_int {
+    luaL_loadbufferx(L, s, sz, n, ptr::null())
+}
+
+#[inline(always)]
+pub unsafe fn luaL_pushfail(L: *mut lua_State) {
+    lua_pushnil(L)
+}
+
+// Standard libraries (`lualib.h`)
+
+pub const LUA_COLIBNAME: &str = "coroutine";
+pub const LUA_TABLIBNAME: &str = "table";
+pub const LUA_IOLIBNAME: &str = "io";
+pub const LUA_OSLIBNAME: &str = "os";
+pub const LUA_STRLIBNAME: &str = "string";
+pub const LUA_UTF8LIBNAME: &str = "utf8";
+pub const LUA_MATHLIBNAME: &str = "math";
+pub const LUA_DBLIBNAME: &str = "debug";
+pub const LUA_LOADLIBNAME: &str = "package";
+
+extern "C-unwind" {
+    pub fn luaopen_base(L: *mut lua_State) -> c_int;
+    pub fn luaopen_coroutine(L: *mut lua_State) -> c_int;
+    pub fn luaopen_table(L: *mut lua_State) -> c_int;
+    pub fn luaopen_io(L: *mut lua_State) -> c_int;
+    pub fn luaopen_os(L: *mut lua_State) -> c_int;
+    pub fn luaopen_string(L: *mut lua_State) -> c_int;
+    pub fn luaopen_utf8(L: *mut lua_State) -> c_int;
+    pub fn luaopen_math(L: *mut lua_State) -> c_int;
+    pub fn luaopen_debug(L: *mut lua_State) -> c_int;
+    pub fn luaopen_package(L: *mut lua_State) -> c_int;
+
+    // Pluto libraries, see `PlutoLib`
+    pub fn luaopen_crypto(L: *mut lua_State) -> c_int;
+    pub fn luaopen_json(L: *mut lua_State) -> c_int;
+    pub fn luaopen_base32(L: *mut lua_State) -> c_int;
+    pub fn luaopen_base64(L: *mut lua_State) -> c_int;
+    pub fn luaopen_assert(L: *mut lua_State) -> c_int;
+    pub fn luaopen_vector3(L: *mut lua_State) -> c_int;
+    pub fn luaopen_url(L: *mut lua_State) -> c_int;
+    pub fn luaopen_star(L: *mut lua_State) -> c_int;
+    pub fn luaopen_cat(L: *mut lua_State) -> c_int;
+    pub fn luaopen_http(L: *mut lua_State) -> c_int;
+    pub fn luaopen_scheduler(L: *mut lua_State) -> c_int;
+    /// Not available on Emscripten.
+    pub fn luaopen_socket(L: *mut lua_State) -> c_int;
+    pub fn luaopen_bigint(L: *mut lua_State) -> c_int;
+    pub fn luaopen_xml(L: *mut lua_State) -> c_int;
+    pub fn luaopen_regex(L: *mut lua_State) -> c_int;
+    pub fn luaopen_ffi(L: *mut lua_State) -> c_int;
+    pub fn luaopen_canvas(L: *mut lua_State) -> c_int;
+
+    /// Opens all standard libraries and registers the preloaded Pluto libraries.
+    pub fn luaL_openlibs(L: *mut lua_State);
+}
@@ -1,7 +1,32 @@
+use std::collections::{BTreeSet, HashSet};
 use std::env;
+use std::error::Error as StdError;
+use std::fmt;
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
 
+mod check;
+mod precompile;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+pub use check::{check_scripts, CheckReport, ScriptCheck};
+pub use precompile::{Bytecode, Precompile};
+
+/// Configures and builds the Pluto library, and optionally the `pluto` and `plutoc` executables.
+///
+/// # Host functions
+///
+/// Hooks such as [`Build::load_hook`] or [`IlpMode::Hook`] name a function provided by the
+/// program that links Pluto. Each one is declared with C linkage in a header included by
+/// every Pluto source, so it must be exported under that exact name (`#[no_mangle]` in Rust)
+/// and is resolved when the final binary is linked. Hooks may raise Lua errors, which needs
+/// the `C-unwind` ABI in Rust, and can't be used with [`LinkKind::Dynamic`].
+#[derive(Clone)]
 pub struct Build {
     out_dir: Option<PathBuf>,
     target: Option<String>,
@@ -18,12 +43,190 @@ pub struct Build {
     disable_os_exec: Option<bool>,
     // Disable loading any C modules or shared libraries
     disable_binaries: Option<bool>,
+    // Exclude the `debug` library
+    disable_debuglib: Option<bool>,
+    // Exclude the `coroutine` library
+    disable_corolib: Option<bool>,
+    // Make all HTTP requests fail
+    disable_http: Option<bool>,
+    // Disable `load` with a reader function
+    disable_unmoderated_load: Option<bool>,
+    // Don't cache the length of tables
+    disable_length_cache: Option<bool>,
+    // Exclude `table.freeze` and frozen tables
+    disable_table_freezing: Option<bool>,
+    // Run the parts of the standard library written in Pluto
+    load_pluto_stdlib_code: Option<bool>,
+    // Script run by `luaL_openlibs` instead of the built-in startup code
+    prelude: Option<PathBuf>,
+    // Enable the parser warnings that are off by default
+    parser_warnings: Option<bool>,
+    // Leave code snippets out of error messages
+    short_errors: Option<bool>,
+    // Use ANSI color codes in error messages
+    colored_errors: Option<bool>,
+    // Allow changing the error message style of each state at runtime
+    per_state_error_style: Option<bool>,
+    // Keywords and syntax options
+    dialect: Option<Dialect>,
+    // Max number of bytes allocated by states created with `luaL_newstate`
+    memory_limit: Option<usize>,
+    // Allow changing the memory limit of each state at runtime
+    per_state_memory_limit: Option<bool>,
+    // Infinite loop prevention
+    ilp: Option<IlpConfig>,
+    // Max execution time of a state
+    execution_time_limit: Option<Duration>,
+    // Host function called when the execution time limit is exceeded
+    execution_time_limit_hook: Option<String>,
+    // Print the executed VM instructions
+    vm_dump: Option<VmDumpConfig>,
+    // Host function moderating `load`
+    load_hook: Option<String>,
+    // Host function moderating `loadfile`, `dofile` and `require` of Lua files
+    loadfile_hook: Option<String>,
+    // Host function moderating loading of C libraries
+    loadclib_hook: Option<String>,
+    // Host function moderating file reads
+    read_file_hook: Option<String>,
+    // Host function moderating file writes
+    write_file_hook: Option<String>,
+    // Host function moderating (or answering) HTTP requests
+    http_request_hook: Option<String>,
+    // Host function moderating calls made through the `ffi` library
+    ffi_call_hook: Option<String>,
+    // Libraries registered in `package.preload` (all if not set)
+    preloaded_libs: Option<Vec<PlutoLib>>,
+    // Build the `pluto` and `plutoc` executables for the host
+    build_cli: Option<bool>,
+    // Link Pluto as a static or shared library
+    link_kind: Option<LinkKind>,
+    // Prefix of the exported C API symbols
+    symbol_prefix: Option<String>,
+}
+
+/// How Pluto is linked, see [`Build::link_kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    /// Static `libpluto.a` and `libsoup.a` archives (`pluto.lib` and `soup.lib` for MSVC).
+    Static,
+    /// A single shared library: `libpluto.so`, `libpluto.dylib` or `pluto.dll`.
+    Dynamic,
+}
+
+/// A library that Pluto registers in `package.preload`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlutoLib {
+    Assert,
+    Base32,
+    Base64,
+    Bigint,
+    Canvas,
+    Cat,
+    Crypto,
+    Ffi,
+    Http,
+    Json,
+    Regex,
+    Scheduler,
+    Socket,
+    /// The `*` library, which requires every other preloaded library.
+    Star,
+    Url,
+    Vector3,
+    Xml,
 }
 
 pub struct Artifacts {
     lib_dir: PathBuf,
     libs: Vec<String>,
     cpp_stdlib: Option<String>,
+    pluto_bin: Option<PathBuf>,
+    plutoc_bin: Option<PathBuf>,
+    link_kind: LinkKind,
+    shared_lib: Option<PathBuf>,
+    include_dir: PathBuf,
+    defines: Vec<(String, Option<String>)>,
+    symbol_prefix: Option<String>,
+    ffi_module: PathBuf,
+    prelude: Option<PathBuf>,
+}
+
+/// An error that occurred while building Pluto.
+#[derive(Debug)]
+pub enum Error {
+    /// A required environment variable (`TARGET`, `HOST` or `OUT_DIR`) is not set.
+    MissingEnv(&'static str),
+    /// An I/O operation on the source or output directory failed.
+    Io { path: PathBuf, source: io::Error },
+    /// Linking the `pluto` or `plutoc` executable, or the shared library, failed.
+    Link { bin: String, message: String },
+    /// The combination of options is not supported.
+    InvalidConfig(String),
+    /// Bytecode can't be precompiled because the build disables loading it.
+    BytecodeDisabled,
+    /// Bytecode compiled on the host can't be loaded on the target.
+    IncompatibleBytecode { target: String, host: String },
+    /// `plutoc` failed to compile a script.
+    Precompile { script: PathBuf, message: String },
+    /// The C++ compiler failed while building a library.
+    ///
+    /// `file` is the translation unit that failed to compile, when it can be determined.
+    Compile {
+        lib: String,
+        file: Option<PathBuf>,
+        source: cc::Error,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingEnv(var) => write!(f, "{var} not set"),
+            Error::Io { path, source } => write!(f, "I/O error on {}: {source}", path.display()),
+            Error::Link { bin, message } => write!(f, "failed to link {bin}: {message}"),
+            Error::InvalidConfig(message) => write!(f, "invalid configuration: {message}"),
+            Error::BytecodeDisabled => {
+                write!(f, "cannot precompile scripts: bytecode is disabled")
+            }
+            Error::IncompatibleBytecode { target, host } => write!(
+                f,
+                "bytecode compiled on {host} is incompatible with {target}"
+            ),
+            Error::Precompile { script, message } => {
+                write!(f, "failed to precompile {}: {message}", script.display())
+            }
+            Error::Compile {
+                lib,
+                file: Some(file),
+                source,
+            } => write!(
+                f,
+                "failed to compile {} (lib{lib}): {source}",
+                file.display()
+            ),
+            Error::Compile {
+                lib,
+                file: None,
+                source,
+            } => write!(f, "failed to compile lib{lib}: {source}"),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::MissingEnv(_)
+            | Error::Link { .. }
+            | Error::InvalidConfig(_)
+            | Error::BytecodeDisabled
+            | Error::IncompatibleBytecode { .. }
+            | Error::Precompile { .. } => None,
+            Error::Io { source, .. } => Some(source),
+            Error::Compile { source, .. } => Some(source),
+        }
+    }
 }
 
 impl Build {
@@ -39,6 +242,36 @@ impl Build {
             disable_fs: None,
             disable_os_exec: None,
             disable_binaries: None,
+            disable_debuglib: None,
+            disable_corolib: None,
+            disable_http: None,
+            disable_unmoderated_load: None,
+            disable_length_cache: None,
+            disable_table_freezing: None,
+            load_pluto_stdlib_code: None,
+            prelude: None,
+            parser_warnings: None,
+            short_errors: None,
+            colored_errors: None,
+            per_state_error_style: None,
+            dialect: None,
+            memory_limit: None,
+            per_state_memory_limit: None,
+            ilp: None,
+            execution_time_limit: None,
+            execution_time_limit_hook: None,
+            vm_dump: None,
+            load_hook: None,
+            loadfile_hook: None,
+            loadclib_hook: None,
+            read_file_hook: None,
+            write_file_hook: None,
+            http_request_hook: None,
+            ffi_call_hook: None,
+            preloaded_libs: None,
+            build_cli: None,
+            link_kind: None,
+            symbol_prefix: None,
         }
     }
 
@@ -91,19 +324,356 @@ impl Build {
         self
     }
 
+    /// Removes the `debug` library.
+    ///
+    /// The parts of the standard library written in Pluto keep working, as they get
+    /// `debug.getinfo` without the global. Controls `PLUTO_NO_DEBUGLIB` define.
+    pub fn disable_debuglib(&mut self, disable: bool) -> &mut Build {
+        self.disable_debuglib = Some(disable);
+        self
+    }
+
+    // Controls `PLUTO_NO_COROLIB` define
+    pub fn disable_corolib(&mut self, disable: bool) -> &mut Build {
+        self.disable_corolib = Some(disable);
+        self
+    }
+
+    // Controls `PLUTO_DISABLE_HTTP_COMPLETELY` define
+    pub fn disable_http(&mut self, disable: bool) -> &mut Build {
+        self.disable_http = Some(disable);
+        self
+    }
+
+    // Controls `PLUTO_DISABLE_UNMODERATED_LOAD` define
+    pub fn disable_unmoderated_load(&mut self, disable: bool) -> &mut Build {
+        self.disable_unmoderated_load = Some(disable);
+        self
+    }
+
+    /// Stops caching the length of tables, which saves a field in every table.
+    ///
+    /// The length operator then looks for the border of the table on each use, as in Lua.
+    /// `lua_setcachelen` is left out. Controls `PLUTO_DISABLE_LENGTH_CACHE` define.
+    pub fn disable_length_cache(&mut self, disable: bool) -> &mut Build {
+        self.disable_length_cache = Some(disable);
+        self
+    }
+
+    /// Leaves out table freezing: `table.freeze`, `table.isfrozen` and the frozen checks
+    /// on every table write.
+    ///
+    /// `lua_freezetable`, `lua_istablefrozen` and `lua_erriffrozen` are left out.
+    /// Controls `PLUTO_DISABLE_TABLE_FREEZING` define.
+    pub fn disable_table_freezing(&mut self, disable: bool) -> &mut Build {
+        self.disable_table_freezing = Some(disable);
+        self
+    }
+
+    /// Runs the parts of the standard library that are written in Pluto (enabled by default).
+    ///
+    /// When disabled, `luaL_openlibs` no longer defines `exception` and `instanceof`,
+    /// `table.min`, `table.max` and `socket.bind` are left out, and the `assert`, `scheduler`,
+    /// `vector3` and `*` libraries are empty. This also saves running the startup code in every
+    /// new state. Controls `PLUTO_DONT_LOAD_ANY_STANDARD_LIBRARY_CODE_WRITTEN_IN_PLUTO` define.
+    pub fn load_pluto_stdlib_code(&mut self, load: bool) -> &mut Build {
+        self.load_pluto_stdlib_code = Some(load);
+        self
+    }
+
+    /// Sets a Pluto script that `luaL_openlibs` runs in place of the built-in startup code
+    /// (which defines `exception` and `instanceof`).
+    ///
+    /// The script is embedded at build time, see [`Artifacts::print_cargo_metadata`].
+    /// It runs whether or not [`Build::load_pluto_stdlib_code`] is enabled, and its errors
+    /// are raised by `luaL_openlibs`. Controls `PLUTO_PRELUDE` define.
+    pub fn prelude<P: AsRef<Path>>(&mut self, path: P) -> &mut Build {
+        self.prelude = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Enables the parser warnings that are off by default: `global-shadow`,
+    /// `non-portable-code`, `non-portable-bytecode` and `non-portable-name`.
+    ///
+    /// Scripts can still toggle them with `@pluto_warnings`.
+    /// Controls `PLUTO_WARN_GLOBAL_SHADOW` and `PLUTO_WARN_NON_PORTABLE_*` defines.
+    pub fn parser_warnings(&mut self, enable: bool) -> &mut Build {
+        self.parser_warnings = Some(enable);
+        self
+    }
+
+    /// Leaves the code snippets (and notes) out of syntax errors and parser warnings,
+    /// so that they fit on a single line.
+    ///
+    /// Controls `PLUTO_SHORT_ERRORS` define.
+    pub fn short_errors(&mut self, enable: bool) -> &mut Build {
+        self.short_errors = Some(enable);
+        self
+    }
+
+    /// Highlights syntax errors and parser warnings with ANSI color codes.
+    ///
+    /// Controls `PLUTO_USE_COLORED_OUTPUT` define.
+    pub fn colored_errors(&mut self, enable: bool) -> &mut Build {
+        self.colored_errors = Some(enable);
+        self
+    }
+
+    /// Allows changing the style of error messages of each state at runtime with
+    /// `pluto_set_error_style(lua_State *L, int style)`, where `style` combines the
+    /// `PLUTO_ERRSTYLE_SHORT` and `PLUTO_ERRSTYLE_COLOR` flags.
+    ///
+    /// New states start with the style set by [`Build::short_errors`] and
+    /// [`Build::colored_errors`]. Controls `PLUTO_ERROR_STYLE_PER_STATE` define.
+    pub fn per_state_error_style(&mut self, enable: bool) -> &mut Build {
+        self.per_state_error_style = Some(enable);
+        self
+    }
+
+    /// Sets how Pluto's keywords coexist with plain Lua code, see [`Dialect`].
+    ///
+    /// Controls `PLUTO_COMPATIBLE_*`, `PLUTO_USE_LET`, `PLUTO_USE_CONST`, `PLUTO_USE_GLOBAL` and
+    /// `PLUTO_PARANOID_KEYWORD_DETECTION` defines.
+    pub fn dialect(&mut self, dialect: Dialect) -> &mut Build {
+        self.dialect = Some(dialect);
+        self
+    }
+
+    // Controls `PLUTO_MEMORY_LIMIT` define
+    pub fn memory_limit(&mut self, bytes: usize) -> &mut Build {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Enables Pluto's infinite loop prevention (`PLUTO_ILP_ENABLE`).
+    pub fn infinite_loop_protection(&mut self, config: IlpConfig) -> &mut Build {
+        self.ilp = Some(config);
+        self
+    }
+
+    /// Enables Pluto's execution time limit (`PLUTO_ETL_ENABLE`).
+    ///
+    /// The deadline is counted from the creation of a state. Once it's exceeded,
+    /// the VM raises the "Execution time limit exceeded" error. Limits longer than about
+    /// 146 years are shortened to that, so the deadline fits in 64-bit nanoseconds.
+    pub fn execution_time_limit(&mut self, limit: Duration) -> &mut Build {
+        self.execution_time_limit = Some(limit);
+        self
+    }
+
+    /// Sets the host function that is called when the execution time limit is exceeded.
+    ///
+    /// The function has the signature `void (lua_State *L)` and can raise its own Lua error,
+    /// otherwise the default one is raised. Controls `PLUTO_ETL_TIMESUP` define.
+    pub fn execution_time_limit_hook(&mut self, symbol: &str) -> &mut Build {
+        self.execution_time_limit_hook = Some(symbol.to_string());
+        self
+    }
+
+    /// Prints every VM instruction that is executed to stdout, for debugging.
+    ///
+    /// This slows down the VM considerably and is not meant for production builds.
+    /// It also prefixes the output of `print` with `<OUTPUT>`.
+    /// Controls `PLUTO_VMDUMP`, `PLUTO_VMDUMP_WHITELIST` and `PLUTO_VMDUMP_COND` defines.
+    pub fn vm_dump(&mut self, config: VmDumpConfig) -> &mut Build {
+        self.vm_dump = Some(config);
+        self
+    }
+
+    /// Allows changing the memory limit of each state at runtime with
+    /// `pluto_set_memory_limit(lua_State *L, size_t limit)`.
+    ///
+    /// New states start with the limit set by [`Build::memory_limit`] (unlimited if not set).
+    /// Controls `PLUTO_MEMORY_LIMIT_PER_STATE` define.
+    pub fn per_state_memory_limit(&mut self, enable: bool) -> &mut Build {
+        self.per_state_memory_limit = Some(enable);
+        self
+    }
+
+    /// Sets the host function that moderates code loaded by `load`.
+    ///
+    /// The function has the signature `bool (lua_State *L, const char *code)` and returning
+    /// `false` raises a Lua error. Controls `PLUTO_LOAD_HOOK` define.
+    pub fn load_hook(&mut self, symbol: &str) -> &mut Build {
+        self.load_hook = Some(symbol.to_string());
+        self
+    }
+
+    /// Sets the host function that moderates files loaded by `loadfile`, `dofile` and `require`.
+    ///
+    /// The function has the signature `bool (lua_State *L, const char *filename)`, where
+    /// `filename` is `NULL` for the standard input, and returning `false` makes loading fail.
+    /// Controls `PLUTO_LOADFILE_HOOK` define.
+    pub fn loadfile_hook(&mut self, symbol: &str) -> &mut Build {
+        self.loadfile_hook = Some(symbol.to_string());
+        self
+    }
+
+    /// Sets the host function that moderates C libraries loaded by `require` and `package.loadlib`.
+    ///
+    /// The function has the signature `bool (lua_State *L, const char *path)` and returning
+    /// `false` raises a Lua error. Controls `PLUTO_LOADCLIB_HOOK` define.
+    pub fn loadclib_hook(&mut self, symbol: &str) -> &mut Build {
+        self.loadclib_hook = Some(symbol.to_string());
+        self
+    }
+
+    /// Sets the host function that moderates any attempt to read a file's contents or metadata
+    /// through the `io` library and `io`/`os` file functions.
+    ///
+    /// The function has the signature `bool (lua_State *L, const char *path)`, where `path` is
+    /// UTF-8 encoded, and returning `false` raises a Lua error.
+    /// Controls `PLUTO_READ_FILE_HOOK` define.
+    pub fn read_file_hook(&mut self, symbol: &str) -> &mut Build {
+        self.read_file_hook = Some(symbol.to_string());
+        self
+    }
+
+    /// Sets the host function that moderates any attempt to write a file's contents or metadata.
+    ///
+    /// The function has the same signature as the [`Build::read_file_hook`] one.
+    /// Controls `PLUTO_WRITE_FILE_HOOK` define.
+    pub fn write_file_hook(&mut self, symbol: &str) -> &mut Build {
+        self.write_file_hook = Some(symbol.to_string());
+        self
+    }
+
+    /// Sets the host function that is called for every `http.request` before it's sent.
+    ///
+    /// The function has the signature `bool (lua_State *L, const char *url)`. Returning
+    /// `false` denies the request with a Lua error. Returning `true` lets it through, unless
+    /// the function pushed values for `http.request` to return instead of sending it
+    /// (e.g. `body, status_code, headers, status_text`). Controls `PLUTO_HTTP_REQUEST_HOOK`
+    /// define.
+    ///
+    /// Use [`Build::disable_http`] to make every request fail without a host function.
+    pub fn http_request_hook(&mut self, symbol: &str) -> &mut Build {
+        self.http_request_hook = Some(symbol.to_string());
+        self
+    }
+
+    /// Sets the host function that is called before every native function call made
+    /// through the `ffi` library.
+    ///
+    /// The function has the signature `bool (lua_State *L, void *addr)`, where `addr` is the
+    /// address of the function about to be called, and returning `false` raises a Lua error.
+    /// Controls `PLUTO_FFI_CALL_HOOK` define.
+    pub fn ffi_call_hook(&mut self, symbol: &str) -> &mut Build {
+        self.ffi_call_hook = Some(symbol.to_string());
+        self
+    }
+
+    /// Removes the `ffi` library from the preloaded set (or puts it back).
+    ///
+    /// Unlike [`Build::disable_binaries`], this keeps `package.loadlib` and C modules working.
+    pub fn disable_ffi(&mut self, disable: bool) -> &mut Build {
+        if disable {
+            self.exclude_lib(PlutoLib::Ffi);
+        } else if let Some(ref mut libs) = self.preloaded_libs {
+            if !libs.contains(&PlutoLib::Ffi) {
+                libs.push(PlutoLib::Ffi);
+            }
+        }
+        self
+    }
+
+    /// Applies a preset combination of sandboxing options.
+    ///
+    /// Options set after this call take precedence over the profile.
+    pub fn sandbox(&mut self, profile: SandboxProfile) -> &mut Build {
+        match profile {
+            SandboxProfile::Restricted => {
+                self.disable_fs(true)
+                    .disable_os_exec(true)
+                    .disable_binaries(true)
+                    .disable_http(true);
+            }
+            SandboxProfile::Untrusted => {
+                self.sandbox(SandboxProfile::Restricted)
+                    .disable_bytecode(true)
+                    .disable_debuglib(true)
+                    .disable_unmoderated_load(true)
+                    .memory_limit(SandboxProfile::UNTRUSTED_MEMORY_LIMIT);
+            }
+        }
+        self
+    }
+
+    /// Sets the libraries that are registered in `package.preload` (all by default).
+    ///
+    /// Sources of the libraries that are left out are not compiled.
+    pub fn preloaded_libs(&mut self, libs: &[PlutoLib]) -> &mut Build {
+        self.preloaded_libs = Some(libs.to_vec());
+        self
+    }
+
+    /// Removes a library from the preloaded set.
+    pub fn exclude_lib(&mut self, lib: PlutoLib) -> &mut Build {
+        let libs = self
+            .preloaded_libs
+            .get_or_insert_with(|| PlutoLib::ALL.to_vec());
+        libs.retain(|&l| l != lib);
+        self
+    }
+
+    /// Also builds the `pluto` interpreter and the `plutoc` bytecode compiler for the host,
+    /// see [`Artifacts::pluto_bin`] and [`Artifacts::plutoc_bin`].
+    ///
+    /// The executables use the same options as the library, except for host-provided
+    /// functions (hooks), which can't be linked into them and are left out, and
+    /// [`Build::vm_dump`]. When cross-compiling (or when either is set), Pluto is compiled
+    /// a second time for the host.
+    ///
+    /// The front-end sources (`lua.cpp` and `luac.cpp`) define `main` and are never part of
+    /// the static library; they are only compiled in this mode.
+    pub fn build_cli(&mut self, enable: bool) -> &mut Build {
+        self.build_cli = Some(enable);
+        self
+    }
+
+    /// Sets how Pluto is linked, [`LinkKind::Static`] by default.
+    ///
+    /// A shared library exports the `lua_*`, `luaL_*` and `luaopen_*` API, so that C modules
+    /// loaded with `package.loadlib` and several processes can share one runtime.
+    /// Host-provided functions (hooks) can't be resolved from a shared library and are not
+    /// supported. Controls `LUA_BUILD_AS_DLL` define on Windows.
+    pub fn link_kind(&mut self, kind: LinkKind) -> &mut Build {
+        self.link_kind = Some(kind);
+        self
+    }
+
+    /// Prefixes every exported C symbol (`lua_*`, `luaL_*`, `luaopen_*` and a few internal
+    /// tables), e.g. `pluto_lua_newstate` for `"pluto_"`, so that Pluto can be linked into
+    /// the same binary as another Lua implementation.
+    ///
+    /// The symbols are renamed with macros from the generated `pluto_rename.h`, which is also
+    /// included by the headers in [`Artifacts::include_dir`], so C/C++ code compiled against
+    /// them needs no changes. Rust code should use [`Artifacts::ffi_module`].
+    pub fn symbol_prefix(&mut self, prefix: &str) -> &mut Build {
+        self.symbol_prefix = Some(prefix.to_string());
+        self
+    }
+
+    /// Builds Pluto, panicking on failure.
+    ///
+    /// See [`Build::try_build`] for a non-panicking version.
     pub fn build(&mut self) -> Artifacts {
-        let target = &self.target.as_ref().expect("TARGET not set")[..];
-        let host = &self.host.as_ref().expect("HOST not set")[..];
-        let out_dir = self.out_dir.as_ref().expect("OUT_DIR not set");
+        match self.try_build() {
+            Ok(artifacts) => artifacts,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    /// Builds Pluto, returning an error instead of panicking.
+    pub fn try_build(&mut self) -> Result<Artifacts, Error> {
+        self.validate()?;
+        let target = &self.target.as_ref().ok_or(Error::MissingEnv("TARGET"))?[..];
+        let host = &self.host.as_ref().ok_or(Error::MissingEnv("HOST"))?[..];
+        let out_dir = self.out_dir.as_ref().ok_or(Error::MissingEnv("OUT_DIR"))?;
 
         let pluto_source_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("pluto");
         let soup_source_dir = pluto_source_dir.join("vendor").join("Soup");
 
-        // Cleanup
-        if out_dir.exists() {
-            fs::remove_dir_all(out_dir).unwrap();
-        }
-
         // Configure C++
         let mut config = cc::Build::new();
         config
@@ -126,15 +696,36 @@ impl Build {
             config.flag_if_supported("-fno-math-errno");
         }
 
+        let preloaded_libs = self.preloaded_libs.as_deref().unwrap_or(PlutoLib::ALL);
+        // The front-ends define `main` and are only compiled for the command-line tools
+        let (frontend_files, mut pluto_files): (Vec<_>, Vec<_>) =
+            (files_by_ext(&pluto_source_dir, "cpp")?.into_iter()).partition(|file| {
+                let file_name = file.file_name().and_then(|n| n.to_str());
+                file_name.is_some_and(|name| FRONTEND_SOURCES.contains(&name))
+            });
+        pluto_files.retain(|file| {
+            let file_name = file.file_name().and_then(|n| n.to_str());
+            PlutoLib::ALL
+                .iter()
+                .filter(|lib| Some(lib.source_file()) == file_name)
+                .all(|lib| lib.is_required_by(preloaded_libs))
+        });
+
         // Build Soup
         let soup_lib_name = "soup";
         let mut soup_config = config.clone();
-        soup_config.add_files_by_ext(&soup_source_dir.join("soup"), "cpp");
+        // Only the parts of Soup that are (transitively) used by Pluto sources
+        let build_cli = self.build_cli == Some(true);
+        let soup_users = match build_cli {
+            true => [&pluto_files[..], &frontend_files[..]].concat(),
+            false => pluto_files.clone(),
+        };
+        let mut soup_files = soup_sources(&soup_users, &soup_source_dir.join("soup"))?;
         match target {
             _ if target.contains("x86_64") => {
+                soup_files.extend(files_by_ext(&soup_source_dir.join("Intrin"), "cpp")?);
                 soup_config
                     .define("SOUP_USE_INTRIN", None)
-                    .add_files_by_ext(&soup_source_dir.join("Intrin"), "cpp")
                     .flag_if_supported("-maes")
                     .flag_if_supported("-mpclmul")
                     .flag_if_supported("-mrdrnd")
@@ -143,51 +734,527 @@ impl Build {
                     .flag_if_supported("-msse4.1");
             }
             _ if target.contains("aarch64") => {
+                soup_files.extend(files_by_ext(&soup_source_dir.join("Intrin"), "cpp")?);
                 soup_config
                     .define("SOUP_USE_INTRIN", None)
-                    .add_files_by_ext(&soup_source_dir.join("Intrin"), "cpp")
                     .flag_if_supported("-march=armv8-a+crypto+crc");
             }
             _ => {}
         }
-        soup_config.out_dir(out_dir).compile(soup_lib_name);
+        soup_config.out_dir(out_dir);
+
+        for (name, value) in self.defines() {
+            config.define(&name, value.as_deref());
+        }
+
+        config.out_dir(out_dir);
+
+        // Host-provided functions must be declared with C linkage before Pluto sources use them
+        let extern_decls = self.extern_decls();
+        let link_kind = self.link_kind.unwrap_or(LinkKind::Static);
+        let hooks_header = out_dir.join("pluto_hooks.h");
+        if !extern_decls.is_empty() {
+            force_include(&mut config, &hooks_header)?;
+        }
+
+        // Exported symbols are renamed in every source, including the front-ends
+        let symbol_prefix = self.symbol_prefix.as_deref().filter(|p| !p.is_empty());
+        // The prelude is included by `linit.cpp` only
+        let prelude_dir = out_dir.join("prelude");
+        if self.prelude.is_some() {
+            config.include(&prelude_dir);
+        }
+
+        let include_dir = out_dir.join("include");
+        let rename_header = include_dir.join("pluto_rename.h");
+        if symbol_prefix.is_some() {
+            force_include(&mut config, &rename_header)?;
+        }
+
+        // Start from scratch if the effective configuration has changed since the last build
+        let mut fingerprint =
+            Self::fingerprint(&[(soup_lib_name, &soup_config), ("pluto", &config)])?;
+        fingerprint.push_str(&format!("link {link_kind:?}\n"));
+        if let Some(prefix) = symbol_prefix {
+            // Not part of the command line, only of `pluto_rename.h`
+            fingerprint.push_str(&format!("symbol prefix {prefix}\n"));
+        }
+        let fingerprint_path = out_dir.join("fingerprint");
+        if fs::read_to_string(&fingerprint_path).ok().as_deref() != Some(&*fingerprint) {
+            if out_dir.exists() {
+                fs::remove_dir_all(out_dir).map_err(|err| Error::io(out_dir, err))?;
+            }
+            fs::create_dir_all(out_dir).map_err(|err| Error::io(out_dir, err))?;
+            fs::write(&fingerprint_path, &fingerprint)
+                .map_err(|err| Error::io(&fingerprint_path, err))?;
+        }
+
+        if !extern_decls.is_empty() {
+            let mut header = String::from("// Generated by pluto-src\n#pragma once\n\n");
+            header.push_str("typedef struct lua_State lua_State;\n\nextern \"C\" {\n");
+            for decl in &extern_decls {
+                header.push_str(&format!("{decl}\n"));
+            }
+            header.push_str("}\n");
+            fs::write(&hooks_header, header).map_err(|err| Error::io(&hooks_header, err))?;
+        }
+
+        // Public headers for crates compiling their own modules against this build
+        let defines = self.defines();
+        let renames = match symbol_prefix {
+            Some(prefix) => (exported_symbols(&pluto_source_dir)?.into_iter())
+                .map(|symbol| (format!("{prefix}{symbol}"), symbol))
+                .collect(),
+            None => Vec::new(),
+        };
+        write_include_dir(&pluto_source_dir, &include_dir, &defines, &renames)?;
+        let ffi_module = out_dir.join("ffi.rs");
+        write_ffi_module(&ffi_module, symbol_prefix, &defines)?;
+
+        // Objects are considered stale if any header was modified after them
+        let headers_mtime = newest_header_mtime(&pluto_source_dir)?;
+        let mut pluto_headers_mtime = headers_mtime;
+        if let Some(ref prelude) = self.prelude {
+            write_prelude_header(prelude, &prelude_dir.join("pluto_prelude.h"))?;
+            pluto_headers_mtime = headers_mtime.max(newest_header_mtime(&prelude_dir)?);
+        }
+
+        // Build Soup and Pluto
+        let pluto_lib_name = "pluto";
+        let (libs, shared_lib) = match link_kind {
+            LinkKind::Static => {
+                compile_lib(
+                    &soup_config,
+                    out_dir,
+                    soup_lib_name,
+                    &soup_files,
+                    headers_mtime,
+                )?;
+                compile_lib(
+                    &config,
+                    out_dir,
+                    pluto_lib_name,
+                    &pluto_files,
+                    pluto_headers_mtime,
+                )?;
+                let libs = vec![pluto_lib_name.to_string(), soup_lib_name.to_string()];
+                (libs, None)
+            }
+            LinkKind::Dynamic => {
+                let mut objects = compile_objects(
+                    &soup_config,
+                    out_dir,
+                    soup_lib_name,
+                    &soup_files,
+                    headers_mtime,
+                )?;
+                objects.extend(compile_objects(
+                    &config,
+                    out_dir,
+                    pluto_lib_name,
+                    &pluto_files,
+                    pluto_headers_mtime,
+                )?);
+                let path = link_shared(&config, target, out_dir, pluto_lib_name, &objects)?;
+                (vec![pluto_lib_name.to_string()], Some(path))
+            }
+        };
+
+        // Build the command-line tools
+        let (mut pluto_bin, mut plutoc_bin) = (None, None);
+        if build_cli {
+            let same_config = extern_decls.is_empty() && self.vm_dump.is_none();
+            if target == host && same_config && link_kind == LinkKind::Static {
+                let bin_dir = out_dir.join("bin");
+                let objects =
+                    compile_objects(&config, out_dir, "cli", &frontend_files, headers_mtime)?;
+                let link = |bin: &str, main: &str| {
+                    let main_object = (objects.iter())
+                        .find(|object| object.file_stem().is_some_and(|stem| stem == main))
+                        .ok_or_else(|| Error::Link {
+                            bin: bin.to_string(),
+                            message: format!("missing {main}.cpp in Pluto sources"),
+                        })?;
+                    link_bin(&config, target, &bin_dir, bin, main_object, out_dir, &libs)
+                };
+                pluto_bin = Some(link("pluto", "lua")?);
+                plutoc_bin = Some(link("plutoc", "luac")?);
+            } else {
+                let cli = self.cli_build(host, &out_dir.join("cli")).try_build()?;
+                (pluto_bin, plutoc_bin) = (cli.pluto_bin, cli.plutoc_bin);
+            }
+        }
+
+        Ok(Artifacts {
+            lib_dir: out_dir.to_path_buf(),
+            libs,
+            cpp_stdlib: Self::get_cpp_link_stdlib(target, host),
+            pluto_bin,
+            plutoc_bin,
+            link_kind,
+            shared_lib,
+            include_dir,
+            defines,
+            symbol_prefix: symbol_prefix.map(str::to_string),
+            ffi_module,
+            prelude: self.prelude.clone(),
+        })
+    }
+
+    /// Returns the configuration used to build the command-line tools for the host.
+    ///
+    /// Host-provided functions are not available to the executables, so all hooks are unset.
+    /// The executables use Pluto internals and are always linked statically.
+    fn cli_build(&self, host: &str, out_dir: &Path) -> Build {
+        let mut build = self.clone();
+        build.target = Some(host.to_string());
+        build.out_dir = Some(out_dir.to_path_buf());
+        build.link_kind = None;
+        if let Some(ref mut ilp) = build.ilp {
+            if let IlpMode::Hook(_) = ilp.mode {
+                ilp.mode = IlpMode::Error;
+            }
+            ilp.reset_function = None;
+        }
+        build.execution_time_limit_hook = None;
+        // The dump also changes the output of `print`
+        build.vm_dump = None;
+        build.load_hook = None;
+        build.loadfile_hook = None;
+        build.loadclib_hook = None;
+        build.read_file_hook = None;
+        build.write_file_hook = None;
+        build.http_request_hook = None;
+        build.ffi_call_hook = None;
+        debug_assert!(build.extern_decls().is_empty());
+        build
+    }
+
+    /// Builds `plutoc` for the host in `out_dir` and returns its path.
+    ///
+    /// See [`Build::cli_build`] for how the configuration is adjusted. Errors are not colored.
+    fn build_host_plutoc(&self, host: &str, out_dir: &Path) -> Result<PathBuf, Error> {
+        let mut build = self.cli_build(host, out_dir);
+        build.build_cli = Some(true);
+        // Its output is parsed or reported by the build script
+        build.colored_errors = None;
+        let artifacts = build.try_build()?;
+        artifacts.plutoc_bin.ok_or_else(|| Error::Precompile {
+            script: out_dir.to_path_buf(),
+            message: "plutoc was not built for the host".to_string(),
+        })
+    }
+
+    /// Returns the preprocessor definitions derived from the configured options.
+    pub fn defines(&self) -> Vec<(String, Option<String>)> {
+        let mut defines = Vec::new();
+        let mut define =
+            |name: &str, value: Option<String>| defines.push((name.to_string(), value));
+
+        if let Some(LinkKind::Dynamic) = self.link_kind {
+            if (self.target.as_deref()).is_some_and(|target| target.contains("windows")) {
+                define("LUA_BUILD_AS_DLL", None);
+            }
+        }
 
         if let Some(max_stack_size) = self.max_stack_size {
-            config.define("LUAI_MAXSTACK", &*max_stack_size.to_string());
+            define("LUAI_MAXSTACK", Some(max_stack_size.to_string()));
         }
 
         if let Some(true) = self.use_longjmp {
-            config.define("LUA_USE_LONGJMP", None);
+            define("LUA_USE_LONGJMP", None);
         }
 
         if let Some(true) = self.disable_bytecode {
-            config.define("PLUTO_DISABLE_COMPILED", None);
+            define("PLUTO_DISABLE_COMPILED", None);
         }
 
         if let Some(true) = self.disable_fs {
-            config.define("PLUTO_NO_FILESYSTEM", None);
+            define("PLUTO_NO_FILESYSTEM", None);
         }
 
         if let Some(true) = self.disable_os_exec {
-            config.define("PLUTO_NO_OS_EXECUTE", None);
+            define("PLUTO_NO_OS_EXECUTE", None);
         }
 
         if let Some(true) = self.disable_binaries {
-            config.define("PLUTO_NO_BINARIES", None);
+            define("PLUTO_NO_BINARIES", None);
         }
 
-        // Build Pluto
-        let pluto_lib_name = "pluto";
-        config
-            .add_files_by_ext(&pluto_source_dir, "cpp")
-            .out_dir(out_dir)
-            .compile(pluto_lib_name);
+        if let Some(true) = self.disable_debuglib {
+            define("PLUTO_NO_DEBUGLIB", None);
+        }
 
-        Artifacts {
-            lib_dir: out_dir.to_path_buf(),
-            libs: vec![pluto_lib_name.to_string(), soup_lib_name.to_string()],
-            cpp_stdlib: Self::get_cpp_link_stdlib(target, host),
+        if let Some(true) = self.disable_corolib {
+            define("PLUTO_NO_COROLIB", None);
+        }
+
+        if let Some(true) = self.disable_http {
+            define("PLUTO_DISABLE_HTTP_COMPLETELY", None);
         }
+
+        if let Some(true) = self.disable_unmoderated_load {
+            define("PLUTO_DISABLE_UNMODERATED_LOAD", None);
+        }
+
+        if let Some(true) = self.disable_length_cache {
+            define("PLUTO_DISABLE_LENGTH_CACHE", None);
+        }
+
+        if let Some(true) = self.disable_table_freezing {
+            define("PLUTO_DISABLE_TABLE_FREEZING", None);
+        }
+
+        if let Some(false) = self.load_pluto_stdlib_code {
+            define(
+                "PLUTO_DONT_LOAD_ANY_STANDARD_LIBRARY_CODE_WRITTEN_IN_PLUTO",
+                None,
+            );
+        }
+
+        if self.prelude.is_some() {
+            define("PLUTO_PRELUDE", None);
+        }
+
+        if let Some(true) = self.parser_warnings {
+            define("PLUTO_WARN_GLOBAL_SHADOW", None);
+            define("PLUTO_WARN_NON_PORTABLE_CODE", None);
+            define("PLUTO_WARN_NON_PORTABLE_BYTECODE", None);
+            define("PLUTO_WARN_NON_PORTABLE_NAME", None);
+        }
+
+        if let Some(true) = self.short_errors {
+            define("PLUTO_SHORT_ERRORS", None);
+        }
+
+        if let Some(true) = self.colored_errors {
+            define("PLUTO_USE_COLORED_OUTPUT", None);
+        }
+
+        if let Some(true) = self.per_state_error_style {
+            define("PLUTO_ERROR_STYLE_PER_STATE", None);
+        }
+
+        if let Some(ref dialect) = self.dialect {
+            for keyword in dialect.compatible_keywords.iter() {
+                define(&keyword.compatible_define(), None);
+            }
+            if dialect.use_let {
+                define("PLUTO_USE_LET", None);
+            }
+            if dialect.use_const {
+                define("PLUTO_USE_CONST", None);
+            }
+            if dialect.use_global {
+                define("PLUTO_USE_GLOBAL", None);
+            }
+            if dialect.paranoid {
+                define("PLUTO_PARANOID_KEYWORD_DETECTION", None);
+            }
+        }
+
+        if let Some(memory_limit) = self.memory_limit {
+            define("PLUTO_MEMORY_LIMIT", Some(memory_limit.to_string()));
+        }
+
+        if let Some(true) = self.per_state_memory_limit {
+            define("PLUTO_MEMORY_LIMIT_PER_STATE", None);
+        }
+
+        if let Some(ref ilp) = self.ilp {
+            define("PLUTO_ILP_ENABLE", None);
+            let max_iterations = ilp.max_iterations.min(i32::MAX as u32);
+            define("PLUTO_ILP_MAX_ITERATIONS", Some(max_iterations.to_string()));
+            match ilp.mode {
+                IlpMode::Error => {}
+                IlpMode::SilentBreak => define("PLUTO_ILP_SILENT_BREAK", None),
+                IlpMode::Hook(ref symbol) => {
+                    define("PLUTO_ILP_ERROR", Some(format!("{symbol}(L)")))
+                }
+            }
+            if let Some(ref symbol) = ilp.reset_function {
+                define("PLUTO_ILP_HOOK_FUNCTION", Some(symbol.clone()));
+            }
+        }
+
+        if let Some(limit) = self.execution_time_limit {
+            define("PLUTO_ETL_ENABLE", None);
+            // The deadline is the current time plus the limit, both in nanoseconds
+            let nanos = limit.as_nanos().min(i64::MAX as u128 / 2);
+            define("PLUTO_ETL_NANOS", Some(nanos.to_string()));
+            if let Some(ref symbol) = self.execution_time_limit_hook {
+                let timesup =
+                    format!("{symbol}(L); luaG_runerror(L, \"Execution time limit exceeded\");");
+                define("PLUTO_ETL_TIMESUP", Some(timesup));
+            }
+        }
+
+        if let Some(ref vm_dump) = self.vm_dump {
+            define("PLUTO_VMDUMP", None);
+            if !vm_dump.whitelist.is_empty() {
+                define("PLUTO_VMDUMP_WHITELIST", None);
+                define("vmDumpAllow", Some(vm_dump.whitelist.join(", ")));
+            }
+            if let Some(ref symbol) = vm_dump.condition_symbol {
+                // Used as `PLUTO_VMDUMP_COND(L)`
+                define("PLUTO_VMDUMP_COND", Some(symbol.clone()));
+            }
+        }
+
+        if let Some(ref symbol) = self.load_hook {
+            define("PLUTO_LOAD_HOOK", Some(symbol.clone()));
+        }
+
+        if let Some(ref symbol) = self.loadfile_hook {
+            define("PLUTO_LOADFILE_HOOK", Some(symbol.clone()));
+        }
+
+        if let Some(ref symbol) = self.loadclib_hook {
+            define("PLUTO_LOADCLIB_HOOK", Some(symbol.clone()));
+        }
+
+        if let Some(ref symbol) = self.read_file_hook {
+            define("PLUTO_READ_FILE_HOOK", Some(symbol.clone()));
+        }
+
+        if let Some(ref symbol) = self.write_file_hook {
+            define("PLUTO_WRITE_FILE_HOOK", Some(symbol.clone()));
+        }
+
+        if let Some(ref symbol) = self.http_request_hook {
+            define("PLUTO_HTTP_REQUEST_HOOK", Some(symbol.clone()));
+        }
+
+        if let Some(ref symbol) = self.ffi_call_hook {
+            define("PLUTO_FFI_CALL_HOOK", Some(symbol.clone()));
+        }
+
+        let preloaded_libs = self.preloaded_libs.as_deref().unwrap_or(PlutoLib::ALL);
+        for lib in PlutoLib::ALL {
+            if !preloaded_libs.contains(lib) {
+                define(&lib.exclude_define(), None);
+            }
+        }
+
+        defines
+    }
+
+    /// Rejects option values that can't be built.
+    fn validate(&self) -> Result<(), Error> {
+        // The VM resets its jump counter to 0 on forward jumps, so a limit of 0 is hit by any `if`
+        if let Some(IlpConfig {
+            max_iterations: 0, ..
+        }) = self.ilp
+        {
+            return Err(Error::InvalidConfig(
+                "infinite loop protection needs at least one iteration".to_string(),
+            ));
+        }
+        if self.link_kind == Some(LinkKind::Dynamic) && !self.extern_decls().is_empty() {
+            return Err(Error::InvalidConfig(
+                "host-provided functions (hooks) can't be used with LinkKind::Dynamic".to_string(),
+            ));
+        }
+        // Opcode names are pasted into the source of the VM
+        if let Some(ref vm_dump) = self.vm_dump {
+            let is_opcode = |op: &String| {
+                (op.strip_prefix("OP_")).is_some_and(|name| {
+                    !name.is_empty()
+                        && name
+                            .chars()
+                            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+                })
+            };
+            if let Some(op) = vm_dump.whitelist.iter().find(|op| !is_opcode(op)) {
+                return Err(Error::InvalidConfig(format!(
+                    "`{op}` is not an opcode name"
+                )));
+            }
+        }
+        if let Some(prefix) = self.symbol_prefix.as_deref().filter(|p| !p.is_empty()) {
+            let mut chars = prefix.chars();
+            let is_ident = chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !is_ident {
+                return Err(Error::InvalidConfig(format!(
+                    "symbol prefix `{prefix}` is not a valid C identifier"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns C declarations of the host-provided functions referenced by the defines.
+    fn extern_decls(&self) -> Vec<String> {
+        let mut decls = Vec::new();
+        if let Some(ref ilp) = self.ilp {
+            if let IlpMode::Hook(ref symbol) = ilp.mode {
+                decls.push(format!("void {symbol}(lua_State *L);"));
+            }
+            if let Some(ref symbol) = ilp.reset_function {
+                decls.push(format!("int {symbol}(lua_State *L);"));
+            }
+        }
+        if let (Some(_), Some(symbol)) =
+            (self.execution_time_limit, &self.execution_time_limit_hook)
+        {
+            decls.push(format!("void {symbol}(lua_State *L);"));
+        }
+        if let Some(VmDumpConfig {
+            condition_symbol: Some(ref symbol),
+            ..
+        }) = self.vm_dump
+        {
+            decls.push(format!("bool {symbol}(lua_State *L);"));
+        }
+        if let Some(ref symbol) = self.load_hook {
+            decls.push(format!("bool {symbol}(lua_State *L, const char *code);"));
+        }
+        if let Some(ref symbol) = self.loadfile_hook {
+            decls.push(format!(
+                "bool {symbol}(lua_State *L, const char *filename);"
+            ));
+        }
+        if let Some(ref symbol) = self.loadclib_hook {
+            decls.push(format!("bool {symbol}(lua_State *L, const char *path);"));
+        }
+        for symbol in [&self.read_file_hook, &self.write_file_hook]
+            .into_iter()
+            .flatten()
+        {
+            decls.push(format!("bool {symbol}(lua_State *L, const char *path);"));
+        }
+        if let Some(ref symbol) = self.http_request_hook {
+            decls.push(format!(
+                "// Returns false to deny the request, or true to send it or, if values were \
+                 pushed, to return them\nbool {symbol}(lua_State *L, const char *url);"
+            ));
+        }
+        if let Some(ref symbol) = self.ffi_call_hook {
+            decls.push(format!("bool {symbol}(lua_State *L, void *addr);"));
+        }
+        decls
+    }
+
+    /// Describes everything that affects the produced objects: the crate version and
+    /// the full compiler command line (including defines and flags) for each library.
+    fn fingerprint(configs: &[(&str, &cc::Build)]) -> Result<String, Error> {
+        let mut fingerprint = format!("pluto-src {}\n", env!("CARGO_PKG_VERSION"));
+        for (lib, config) in configs {
+            let compiler = config.try_get_compiler().map_err(|source| Error::Compile {
+                lib: lib.to_string(),
+                file: None,
+                source,
+            })?;
+            fingerprint.push_str(&format!("[{lib}] {}", compiler.path().display()));
+            for arg in compiler.args() {
+                fingerprint.push_str(&format!(" {}", arg.to_string_lossy()));
+            }
+            fingerprint.push('\n');
+        }
+        Ok(fingerprint)
     }
 
     /// Returns the C++ standard library:
@@ -223,6 +1290,272 @@ impl Build {
     }
 }
 
+/// Configuration of the infinite loop prevention, see [`Build::infinite_loop_protection`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IlpConfig {
+    /// Maximum number of backward jumps (or sequential tail calls) permitted in a single loop.
+    ///
+    /// Must be at least 1.
+    pub max_iterations: u32,
+    /// What happens when a loop exceeds `max_iterations`.
+    pub mode: IlpMode,
+    /// Name of a `lua_CFunction` that resets the iteration counters when called,
+    /// such as a host-provided `wait` function.
+    pub reset_function: Option<String>,
+}
+
+impl Default for IlpConfig {
+    fn default() -> Self {
+        IlpConfig {
+            max_iterations: 1_000_000,
+            mode: IlpMode::Error,
+            reset_function: None,
+        }
+    }
+}
+
+/// Action taken when a loop exceeds [`IlpConfig::max_iterations`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IlpMode {
+    /// Raise the "infinite loop detected" Lua error.
+    Error,
+    /// Silently break out of the loop.
+    SilentBreak,
+    /// Call the named host function with the signature `void (lua_State *L)`.
+    ///
+    /// The function may raise a Lua error, otherwise the loop is broken out of.
+    Hook(String),
+}
+
+/// Keyword options of the Pluto language, see [`Build::dialect`].
+///
+/// The default is Pluto's: its keywords are reserved, except where a script obviously uses
+/// them as names (e.g. `class = 1`), and `let`, `const` and `global` are off.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Dialect {
+    /// Keywords that are only available as `pluto_<keyword>` (e.g. `pluto_switch`), so that
+    /// their names remain valid identifiers as in Lua.
+    ///
+    /// Scripts can still enable them with `pluto_use`.
+    pub compatible_keywords: KeywordSet,
+    /// Enables the (deprecated) `let` keyword, as if every script started with `pluto_use let`.
+    pub use_let: bool,
+    /// Enables the (deprecated) `const` keyword, as if every script started with `pluto_use const`.
+    pub use_const: bool,
+    /// Enables the `global` keyword, as if every script started with `pluto_use global`.
+    pub use_global: bool,
+    /// Treats keywords as names more aggressively, also when followed by `(`, `{` or a string.
+    ///
+    /// Helps with scripts using these names as globals across files or before their definition.
+    pub paranoid: bool,
+}
+
+/// A Pluto keyword that is a valid identifier in Lua.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Keyword {
+    Switch,
+    Continue,
+    Enum,
+    New,
+    Class,
+    Parent,
+    Export,
+    Try,
+    Catch,
+}
+
+/// A set of [`Keyword`]s.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct KeywordSet(u16);
+
+/// Configuration of the VM instruction dump, see [`Build::vm_dump`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VmDumpConfig {
+    /// Opcodes to print, such as `"OP_CALL"` (see `lopcodes.h`). All are printed if empty.
+    pub whitelist: Vec<String>,
+    /// Name of a host function with the signature `bool (lua_State *L)` that decides,
+    /// for each instruction, whether it is printed. All are printed if not set.
+    pub condition_symbol: Option<String>,
+}
+
+/// A preset combination of sandboxing options, see [`Build::sandbox`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxProfile {
+    /// Scripts can't reach the host system.
+    ///
+    /// Removes the `io` library, `os.execute`, `os.remove` and `os.rename`, loading of C modules
+    /// and shared libraries (`package.loadlib`, `ffi.open`), and makes HTTP requests fail.
+    Restricted,
+    /// For running code from untrusted sources.
+    ///
+    /// Everything from [`SandboxProfile::Restricted`], plus: loading of precompiled chunks and
+    /// `load` with a reader function are disabled, the `debug` library is removed and states
+    /// created with `luaL_newstate` are limited to 64 MB of memory.
+    Untrusted,
+}
+
+impl SandboxProfile {
+    const UNTRUSTED_MEMORY_LIMIT: usize = 64_000_000;
+
+    /// Returns the preprocessor definitions that this profile produces.
+    pub fn defines(self) -> Vec<(String, Option<String>)> {
+        let mut build = Build::new();
+        build.sandbox(self);
+        build.defines()
+    }
+}
+
+impl Keyword {
+    /// All keywords that break Lua identifiers.
+    pub const ALL: &'static [Keyword] = &[
+        Keyword::Switch,
+        Keyword::Continue,
+        Keyword::Enum,
+        Keyword::New,
+        Keyword::Class,
+        Keyword::Parent,
+        Keyword::Export,
+        Keyword::Try,
+        Keyword::Catch,
+    ];
+
+    /// Returns the keyword as written in scripts.
+    pub fn name(self) -> &'static str {
+        match self {
+            Keyword::Switch => "switch",
+            Keyword::Continue => "continue",
+            Keyword::Enum => "enum",
+            Keyword::New => "new",
+            Keyword::Class => "class",
+            Keyword::Parent => "parent",
+            Keyword::Export => "export",
+            Keyword::Try => "try",
+            Keyword::Catch => "catch",
+        }
+    }
+
+    /// Returns the define that makes the keyword compatible with Lua identifiers.
+    fn compatible_define(self) -> String {
+        format!("PLUTO_COMPATIBLE_{}", self.name().to_ascii_uppercase())
+    }
+}
+
+impl KeywordSet {
+    /// No keywords.
+    pub const NONE: KeywordSet = KeywordSet(0);
+
+    /// All keywords, like Pluto's `PLUTO_COMPATIBLE_MODE`.
+    pub const ALL: KeywordSet = KeywordSet((1 << Keyword::ALL.len()) - 1);
+
+    /// Returns this set with `keyword` added.
+    pub const fn with(self, keyword: Keyword) -> KeywordSet {
+        KeywordSet(self.0 | 1 << keyword as u16)
+    }
+
+    /// Returns this set with `keyword` removed.
+    pub const fn without(self, keyword: Keyword) -> KeywordSet {
+        KeywordSet(self.0 & !(1 << keyword as u16))
+    }
+
+    pub const fn contains(self, keyword: Keyword) -> bool {
+        self.0 & 1 << keyword as u16 != 0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the keywords in the set, in the order of [`Keyword::ALL`].
+    pub fn iter(self) -> impl Iterator<Item = Keyword> {
+        (Keyword::ALL.iter().copied()).filter(move |&keyword| self.contains(keyword))
+    }
+}
+
+impl FromIterator<Keyword> for KeywordSet {
+    fn from_iter<I: IntoIterator<Item = Keyword>>(iter: I) -> Self {
+        (iter.into_iter()).fold(KeywordSet::NONE, KeywordSet::with)
+    }
+}
+
+impl PlutoLib {
+    /// All preloaded libraries.
+    pub const ALL: &'static [PlutoLib] = &[
+        PlutoLib::Assert,
+        PlutoLib::Base32,
+        PlutoLib::Base64,
+        PlutoLib::Bigint,
+        PlutoLib::Canvas,
+        PlutoLib::Cat,
+        PlutoLib::Crypto,
+        PlutoLib::Ffi,
+        PlutoLib::Http,
+        PlutoLib::Json,
+        PlutoLib::Regex,
+        PlutoLib::Scheduler,
+        PlutoLib::Socket,
+        PlutoLib::Star,
+        PlutoLib::Url,
+        PlutoLib::Vector3,
+        PlutoLib::Xml,
+    ];
+
+    /// Returns the name of the library in `package.preload`.
+    pub fn name(self) -> &'static str {
+        match self {
+            PlutoLib::Assert => "assert",
+            PlutoLib::Base32 => "base32",
+            PlutoLib::Base64 => "base64",
+            PlutoLib::Bigint => "bigint",
+            PlutoLib::Canvas => "canvas",
+            PlutoLib::Cat => "cat",
+            PlutoLib::Crypto => "crypto",
+            PlutoLib::Ffi => "ffi",
+            PlutoLib::Http => "http",
+            PlutoLib::Json => "json",
+            PlutoLib::Regex => "regex",
+            PlutoLib::Scheduler => "scheduler",
+            PlutoLib::Socket => "socket",
+            PlutoLib::Star => "*",
+            PlutoLib::Url => "url",
+            PlutoLib::Vector3 => "vector3",
+            PlutoLib::Xml => "xml",
+        }
+    }
+
+    /// Returns the define that removes the library from `Pluto::all_preloaded`.
+    fn exclude_define(self) -> String {
+        format!("PLUTO_NO_{self:?}LIB").to_uppercase()
+    }
+
+    fn source_file(self) -> &'static str {
+        match self {
+            PlutoLib::Assert => "lassertlib.cpp",
+            PlutoLib::Base32 => "lbase32.cpp",
+            PlutoLib::Base64 => "lbase64.cpp",
+            PlutoLib::Bigint => "lbigint.cpp",
+            PlutoLib::Canvas => "lcanvas.cpp",
+            PlutoLib::Cat => "lcatlib.cpp",
+            PlutoLib::Crypto => "lcryptolib.cpp",
+            PlutoLib::Ffi => "lffi.cpp",
+            PlutoLib::Http => "lhttplib.cpp",
+            PlutoLib::Json => "ljson.cpp",
+            PlutoLib::Regex => "lregex.cpp",
+            PlutoLib::Scheduler => "lschedulerlib.cpp",
+            PlutoLib::Socket => "lsocketlib.cpp",
+            PlutoLib::Star => "lstarlib.cpp",
+            PlutoLib::Url => "lurllib.cpp",
+            PlutoLib::Vector3 => "lvector3lib.cpp",
+            PlutoLib::Xml => "lxml.cpp",
+        }
+    }
+
+    /// Returns `true` if the library sources must be compiled for the given preloaded set.
+    fn is_required_by(self, libs: &[PlutoLib]) -> bool {
+        // `crypto` uses the bigint userdata helpers from `lbigint.cpp`
+        libs.contains(&self) || (self == PlutoLib::Bigint && libs.contains(&PlutoLib::Crypto))
+    }
+}
+
 impl Artifacts {
     pub fn lib_dir(&self) -> &Path {
         &self.lib_dir
@@ -232,30 +1565,692 @@ impl Artifacts {
         &self.libs
     }
 
+    /// Directory with the public headers (`lua.h`, `lauxlib.h`, `lualib.h`, `luaconf.h`
+    /// and `lua.hpp`).
+    ///
+    /// It also contains `pluto_config.h` with the [`Artifacts::defines`] of this build, which is
+    /// included by `luaconf.h`, so C/C++ modules compiled against it share the same ABI.
+    pub fn include_dir(&self) -> &Path {
+        &self.include_dir
+    }
+
+    /// The preprocessor definitions Pluto was built with, see [`Build::defines`].
+    pub fn defines(&self) -> &[(String, Option<String>)] {
+        &self.defines
+    }
+
+    /// Path to the `pluto` interpreter, if built with [`Build::build_cli`].
+    pub fn pluto_bin(&self) -> Option<&Path> {
+        self.pluto_bin.as_deref()
+    }
+
+    /// Path to the `plutoc` bytecode compiler, if built with [`Build::build_cli`].
+    pub fn plutoc_bin(&self) -> Option<&Path> {
+        self.plutoc_bin.as_deref()
+    }
+
+    /// Path to the shared library, if built with [`LinkKind::Dynamic`].
+    pub fn shared_lib(&self) -> Option<&Path> {
+        self.shared_lib.as_deref()
+    }
+
+    /// The prefix of the exported C symbols, see [`Build::symbol_prefix`].
+    pub fn symbol_prefix(&self) -> Option<&str> {
+        self.symbol_prefix.as_deref()
+    }
+
+    /// Path to a Rust module with the bindings of the `ffi` module, linked to the
+    /// symbols of this build (see [`Build::symbol_prefix`]).
+    ///
+    /// It declares `pub mod ffi` and is meant to be used with `include!`.
+    pub fn ffi_module(&self) -> &Path {
+        &self.ffi_module
+    }
+
+    /// Prints the instructions for Cargo to link Pluto, and the metadata for dependent build
+    /// scripts.
+    ///
+    /// `DEP_<links>_DEFINES` is a comma-separated list of `NAME` or `NAME=VALUE` entries. In
+    /// values, `%`, `,`, `\r` and `\n` are percent-encoded (`%25`, `%2C`, `%0D` and `%0A`).
     pub fn print_cargo_metadata(&self) {
         println!("cargo:rustc-link-search=native={}", self.lib_dir.display());
+        let kind = match self.link_kind {
+            LinkKind::Static => "static",
+            LinkKind::Dynamic => "dylib",
+        };
         for lib in self.libs.iter() {
-            println!("cargo:rustc-link-lib=static={}", lib);
+            println!("cargo:rustc-link-lib={}={}", kind, lib);
         }
-        if let Some(ref cpp_stdlib) = self.cpp_stdlib {
+        // The shared library is linked against the C++ standard library itself
+        if let (LinkKind::Static, Some(cpp_stdlib)) = (self.link_kind, &self.cpp_stdlib) {
             println!("cargo:rustc-link-lib={}", cpp_stdlib);
         }
+        // Available to dependents as `DEP_<links>_INCLUDE`, `DEP_<links>_DEFINES` and
+        // `DEP_<links>_SYMBOL_PREFIX`
+        println!("cargo:include={}", self.include_dir.display());
+        let defines = (self.defines.iter())
+            .map(|(name, value)| match value {
+                Some(value) => format!("{name}={}", encode_define_value(value)),
+                None => name.clone(),
+            })
+            .collect::<Vec<_>>();
+        println!("cargo:defines={}", defines.join(","));
+        if let Some(ref prefix) = self.symbol_prefix {
+            println!("cargo:symbol_prefix={prefix}");
+        }
+        if let Some(ref prelude) = self.prelude {
+            println!("cargo:rerun-if-changed={}", prelude.display());
+        }
     }
 }
 
-trait AddFilesByExt {
-    fn add_files_by_ext(&mut self, dir: &Path, ext: &str) -> &mut Self;
+impl Error {
+    fn io(path: &Path, source: io::Error) -> Self {
+        Error::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
 }
 
-impl AddFilesByExt for cc::Build {
-    fn add_files_by_ext(&mut self, dir: &Path, ext: &str) -> &mut Self {
-        for entry in fs::read_dir(dir)
-            .unwrap()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.path().extension() == Some(ext.as_ref()))
-        {
-            self.file(entry.path());
+/// Headers of the Lua C API, copied to [`Artifacts::include_dir`] along with `luaconf.h`.
+const PUBLIC_HEADERS: &[&str] = &["lua.h", "lauxlib.h", "lualib.h", "lua.hpp"];
+
+/// Pluto sources that define `main` for the `pluto` and `plutoc` executables.
+const FRONTEND_SOURCES: &[&str] = &["lua.cpp", "luac.cpp"];
+
+/// Percent-encodes the characters of a define value that can't appear in `cargo:defines`.
+fn encode_define_value(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '%' => encoded.push_str("%25"),
+            ',' => encoded.push_str("%2C"),
+            '\r' => encoded.push_str("%0D"),
+            '\n' => encoded.push_str("%0A"),
+            c => encoded.push(c),
         }
-        self
+    }
+    encoded
+}
+
+/// Embeds the `prelude` script into a header for `linit.cpp`.
+///
+/// The header is only rewritten when its contents change, as `linit.cpp` is recompiled
+/// whenever it is newer than the objects.
+fn write_prelude_header(prelude: &Path, header_path: &Path) -> Result<(), Error> {
+    let script = fs::read(prelude).map_err(|err| Error::io(prelude, err))?;
+    // Error messages refer to the script by its file name
+    let file_name = prelude.file_name().unwrap_or_default().to_string_lossy();
+    let mut chunk_name = String::from("@");
+    for c in file_name.chars() {
+        match c {
+            c if c.is_ascii_alphanumeric() || " -._".contains(c) => chunk_name.push(c),
+            _ => chunk_name.push('?'),
+        }
+    }
+
+    let mut header = format!("// Generated by pluto-src from {}\n", prelude.display());
+    header.push_str("#pragma once\n\n");
+    header.push_str(&format!(
+        "static const char pluto_prelude_name[] = \"{chunk_name}\";\n\n"
+    ));
+    // A byte array works for any contents and size, unlike a string literal
+    header.push_str("static const unsigned char pluto_prelude[] = {");
+    for (i, byte) in script.iter().chain(&[0]).enumerate() {
+        header.push_str(if i % 16 == 0 { "\n  " } else { " " });
+        header.push_str(&format!("{byte:#04x},"));
+    }
+    header.push_str("\n};\n");
+
+    if fs::read_to_string(header_path).ok().as_deref() != Some(&*header) {
+        let dir = header_path.parent().unwrap();
+        fs::create_dir_all(dir).map_err(|err| Error::io(dir, err))?;
+        fs::write(header_path, header).map_err(|err| Error::io(header_path, err))?;
+    }
+    Ok(())
+}
+
+/// Returns all files in `dir` with the given extension, sorted by name.
+fn files_by_ext(dir: &Path, ext: &str) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|err| Error::io(dir, err))? {
+        let path = entry.map_err(|err| Error::io(dir, err))?.path();
+        if path.extension() == Some(ext.as_ref()) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Copies the public headers to `include_dir` and generates `pluto_config.h` from `defines`.
+///
+/// The copied `luaconf.h` includes `pluto_config.h` before anything else. If there are
+/// `renames` (pairs of new and original symbol names), they are written to `pluto_rename.h`,
+/// which `pluto_config.h` includes.
+fn write_include_dir(
+    source_dir: &Path,
+    include_dir: &Path,
+    defines: &[(String, Option<String>)],
+    renames: &[(String, String)],
+) -> Result<(), Error> {
+    fs::create_dir_all(include_dir).map_err(|err| Error::io(include_dir, err))?;
+    for header in PUBLIC_HEADERS {
+        let (from, to) = (source_dir.join(header), include_dir.join(header));
+        fs::copy(&from, &to).map_err(|err| Error::io(&from, err))?;
+    }
+
+    let luaconf_path = source_dir.join("luaconf.h");
+    let luaconf = fs::read_to_string(&luaconf_path).map_err(|err| Error::io(&luaconf_path, err))?;
+    let luaconf = format!("// Configuration of this build, added by pluto-src\n#include \"pluto_config.h\"\n\n{luaconf}");
+    let luaconf_path = include_dir.join("luaconf.h");
+    fs::write(&luaconf_path, luaconf).map_err(|err| Error::io(&luaconf_path, err))?;
+
+    let mut config = String::from("// Generated by pluto-src\n#pragma once\n\n");
+    for (name, value) in defines {
+        match value {
+            Some(value) => config.push_str(&format!("#define {name} {value}\n")),
+            None => config.push_str(&format!("#define {name}\n")),
+        }
+    }
+    if !renames.is_empty() {
+        let mut header = String::from("// Generated by pluto-src\n#pragma once\n\n");
+        for (name, symbol) in renames {
+            header.push_str(&format!("#define {symbol} {name}\n"));
+        }
+        let header_path = include_dir.join("pluto_rename.h");
+        fs::write(&header_path, header).map_err(|err| Error::io(&header_path, err))?;
+        config.push_str("\n#include \"pluto_rename.h\"\n");
+    }
+    let config_path = include_dir.join("pluto_config.h");
+    fs::write(&config_path, config).map_err(|err| Error::io(&config_path, err))
+}
+
+/// Returns the C symbols defined by Pluto: the API declared in the headers and the
+/// variables with C-compatible names (`lua_ident` and the `LUAI_DDEC` tables).
+fn exported_symbols(source_dir: &Path) -> Result<Vec<String>, Error> {
+    const API_MACROS: &[&str] = &[
+        "LUA_API",
+        "LUA_API_NORETURN",
+        "LUALIB_API",
+        "LUALIB_API_NORETURN",
+        "LUAMOD_API",
+    ];
+
+    let mut symbols = BTreeSet::new();
+    for header in files_by_ext(source_dir, "h")? {
+        let contents = fs::read_to_string(&header).map_err(|err| Error::io(&header, err))?;
+        // Only file scope declarations (unindented), the rest is in C++ namespaces
+        for line in contents.lines() {
+            // e.g. `LUA_API int (lua_gettop) (lua_State *L);`
+            let is_api = (line.split_whitespace().next()).is_some_and(|m| API_MACROS.contains(&m));
+            let name = if is_api {
+                line.split_once('(')
+                    .and_then(|(_, rest)| rest.split_once(')'))
+                    .map(|(name, _)| name)
+            } else {
+                // e.g. `LUAI_DDEC(const lu_byte luaP_opmodes[NUM_OPCODES];)`
+                // or `extern const char lua_ident[];`
+                (line.strip_prefix("LUAI_DDEC("))
+                    .or_else(|| line.strip_prefix("extern ").filter(|l| !l.starts_with('"')))
+                    .and_then(|decl| decl.split(['[', ';']).next())
+                    .and_then(|decl| decl.split_whitespace().last())
+            };
+            if let Some(name) = name {
+                symbols.insert(name.trim().to_string());
+            }
+        }
+    }
+    Ok(symbols.into_iter().collect())
+}
+
+/// Writes the `ffi` bindings as `pub mod ffi` to `path`, linked to the symbols renamed
+/// with `symbol_prefix`.
+///
+/// Like the headers, the module leaves out the functions disabled by `defines` and
+/// uses the configured `LUAI_MAXSTACK` for the pseudo-indices.
+fn write_ffi_module(
+    path: &Path,
+    symbol_prefix: Option<&str>,
+    defines: &[(String, Option<String>)],
+) -> Result<(), Error> {
+    let defined = |name: &str| defines.iter().find(|(define, _)| define == name);
+    let available = |function: &str| match function {
+        "pluto_set_memory_limit" => defined("PLUTO_MEMORY_LIMIT_PER_STATE").is_some(),
+        "pluto_set_error_style" | "pluto_get_error_style" => {
+            defined("PLUTO_ERROR_STYLE_PER_STATE").is_some()
+        }
+        "lua_setcachelen" => defined("PLUTO_DISABLE_LENGTH_CACHE").is_none(),
+        "lua_freezetable" | "lua_istablefrozen" | "lua_erriffrozen" => {
+            defined("PLUTO_DISABLE_TABLE_FREEZING").is_none()
+        }
+        _ => true,
+    };
+    let max_stack_size = defined("LUAI_MAXSTACK").and_then(|(_, value)| value.as_deref());
+
+    let mut module = String::from("// Generated by pluto-src\n\npub mod ffi {\n");
+    let mut in_extern_block = false;
+    // Doc comments are held back until the item they document is known to be kept
+    let mut docs = String::new();
+    for line in include_str!("ffi.rs").lines() {
+        if line.starts_with("extern ") {
+            in_extern_block = true;
+        } else if line == "}" {
+            in_extern_block = false;
+        }
+        if in_extern_block && line.trim_start().starts_with("///") {
+            docs.push_str(line);
+            docs.push('\n');
+            continue;
+        }
+        let decl = line.trim_start().strip_prefix("pub fn ");
+        let name = decl.map(|decl| decl.split('(').next().unwrap_or_default());
+        if in_extern_block && name.is_some_and(|name| !available(name)) {
+            docs.clear();
+            continue;
+        }
+        module.push_str(&docs);
+        docs.clear();
+        if let (true, Some(prefix), Some(name)) = (in_extern_block, symbol_prefix, name) {
+            let indent = &line[..line.len() - line.trim_start().len()];
+            module.push_str(&format!("{indent}#[link_name = \"{prefix}{name}\"]\n"));
+        }
+        // Links to this crate can't be resolved from the including crate
+        let mut line = line.to_string();
+        while let Some(start) = line.find("](crate::") {
+            let end = start + line[start..].find(')').unwrap_or_default();
+            let open = line[..start].rfind('[').unwrap_or_default();
+            line = format!(
+                "{}{}{}",
+                &line[..open],
+                &line[open + 1..start],
+                &line[end + 1..]
+            );
+        }
+        if let Some(size) = max_stack_size {
+            if line.starts_with("pub const LUAI_MAXSTACK:") {
+                line = format!("pub const LUAI_MAXSTACK: c_int = {size};");
+            }
+        }
+        module.push_str(&line);
+        module.push('\n');
+    }
+    module.push_str("}\n");
+    fs::write(path, module).map_err(|err| Error::io(path, err))
+}
+
+/// Makes `config` include `header` at the start of every source.
+fn force_include(config: &mut cc::Build, header: &Path) -> Result<(), Error> {
+    let compiler = config.try_get_compiler().map_err(|source| Error::Compile {
+        lib: "pluto".to_string(),
+        file: None,
+        source,
+    })?;
+    if compiler.is_like_msvc() {
+        config.flag(format!("/FI{}", header.display()));
+    } else {
+        config.flag("-include").flag(header);
+    }
+    Ok(())
+}
+
+/// Returns the Soup sources required by `files`.
+///
+/// Follows `#include "..."` directives starting from `files` and picks every Soup
+/// source file whose header is reached, along with the includes of those sources.
+fn soup_sources(files: &[PathBuf], soup_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut visited = HashSet::new();
+    let mut queue = files.to_vec();
+    let mut sources = BTreeSet::new();
+    while let Some(file) = queue.pop() {
+        if !visited.insert(file.clone()) {
+            continue;
+        }
+        let contents = fs::read(&file).map_err(|err| Error::io(&file, err))?;
+        let dir = file.parent().unwrap_or(Path::new(""));
+        for line in String::from_utf8_lossy(&contents).lines() {
+            let include = (line.trim_start().strip_prefix('#'))
+                .and_then(|l| l.trim_start().strip_prefix("include"))
+                .and_then(|l| l.trim_start().strip_prefix('"'))
+                .and_then(|l| l.split('"').next());
+            if let Some(include) = include {
+                let path = dir.join(include);
+                if path.is_file() {
+                    queue.push(path);
+                }
+            }
+        }
+        if file.starts_with(soup_dir) {
+            match file.extension().and_then(|e| e.to_str()) {
+                Some("hpp") => {
+                    let source = file.with_extension("cpp");
+                    if source.is_file() {
+                        queue.push(source);
+                    }
+                }
+                Some("cpp") => {
+                    sources.insert(file);
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(sources.into_iter().collect())
+}
+
+/// Returns the modification time of the newest C/C++ header under `dir` (recursively).
+fn newest_header_mtime(dir: &Path) -> Result<SystemTime, Error> {
+    let mut newest = SystemTime::UNIX_EPOCH;
+    for entry in fs::read_dir(dir).map_err(|err| Error::io(dir, err))? {
+        let entry = entry.map_err(|err| Error::io(dir, err))?;
+        let path = entry.path();
+        let metadata = entry.metadata().map_err(|err| Error::io(&path, err))?;
+        if metadata.is_dir() {
+            newest = newest.max(newest_header_mtime(&path)?);
+        } else if matches!(path.extension().and_then(|e| e.to_str()), Some("h" | "hpp")) {
+            let mtime = metadata.modified().map_err(|err| Error::io(&path, err))?;
+            newest = newest.max(mtime);
+        }
+    }
+    Ok(newest)
+}
+
+/// Compiles `files` into a static library named `lib`.
+///
+/// See [`compile_objects`] for how objects are reused between builds.
+fn compile_lib(
+    config: &cc::Build,
+    out_dir: &Path,
+    lib: &str,
+    files: &[PathBuf],
+    headers_mtime: SystemTime,
+) -> Result<(), Error> {
+    let objects = compile_objects(config, out_dir, lib, files, headers_mtime)?;
+    config
+        .clone()
+        .objects(objects)
+        .try_compile(lib)
+        .map_err(|source| compile_error(lib, files, source))
+}
+
+/// Compiles `files` into objects, returning their paths.
+///
+/// Objects are kept in `<out_dir>/obj/<lib>` between builds and only the files
+/// that were modified after their object (or after `headers_mtime`) are recompiled.
+fn compile_objects(
+    config: &cc::Build,
+    out_dir: &Path,
+    lib: &str,
+    files: &[PathBuf],
+    headers_mtime: SystemTime,
+) -> Result<Vec<PathBuf>, Error> {
+    let obj_dir = out_dir.join("obj").join(lib);
+    fs::create_dir_all(&obj_dir).map_err(|err| Error::io(&obj_dir, err))?;
+
+    let mtime = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut objects = Vec::with_capacity(files.len());
+    let mut stale = Vec::new();
+    for file in files {
+        let mut object_name = file.file_stem().unwrap_or_default().to_os_string();
+        object_name.push(".o");
+        let object = obj_dir.join(object_name);
+        let is_fresh = match (mtime(&object), mtime(file)) {
+            (Some(object_mtime), Some(file_mtime)) => {
+                object_mtime >= file_mtime && object_mtime >= headers_mtime
+            }
+            _ => false,
+        };
+        if !is_fresh {
+            stale.push((file, object.clone()));
+        }
+        objects.push(object);
+    }
+
+    if !stale.is_empty() {
+        let tmp_dir = obj_dir.join("tmp");
+        let compiled = config
+            .clone()
+            .out_dir(&tmp_dir)
+            .files(stale.iter().map(|(file, _)| file))
+            .try_compile_intermediates()
+            .map_err(|source| compile_error(lib, files, source))?;
+        for (compiled, (_, object)) in compiled.iter().zip(&stale) {
+            fs::rename(compiled, object).map_err(|err| Error::io(object, err))?;
+        }
+    }
+
+    Ok(objects)
+}
+
+fn compile_error(lib: &str, files: &[PathBuf], source: cc::Error) -> Error {
+    // `cc` reports the failing command line with quoted arguments, which include the source
+    // file. The file is left out unless exactly one of `files` is an argument.
+    let message = source.to_string();
+    let mut matches = (files.iter()).filter(|file| {
+        let arg = format!(" {:?}", file.as_os_str());
+        message.contains(&arg)
+    });
+    let file = match (matches.next(), matches.next()) {
+        (Some(file), None) => Some(file.clone()),
+        _ => None,
+    };
+    Error::Compile {
+        lib: lib.to_string(),
+        file,
+        source,
+    }
+}
+
+/// Links the executable `bin` from `main_object` and the static `libs` found in `lib_dir`.
+fn link_bin(
+    config: &cc::Build,
+    target: &str,
+    bin_dir: &Path,
+    bin: &str,
+    main_object: &Path,
+    lib_dir: &Path,
+    libs: &[String],
+) -> Result<PathBuf, Error> {
+    let compiler = config.try_get_compiler().map_err(|err| Error::Link {
+        bin: bin.to_string(),
+        message: err.to_string(),
+    })?;
+    fs::create_dir_all(bin_dir).map_err(|err| Error::io(bin_dir, err))?;
+
+    let mut cmd = compiler.to_command();
+    cmd.arg(main_object);
+    let path = if target.contains("windows") {
+        bin_dir.join(format!("{bin}.exe"))
+    } else {
+        bin_dir.join(bin)
+    };
+    if compiler.is_like_msvc() {
+        cmd.arg(format!("/Fe{}", path.display()));
+        cmd.args(libs.iter().map(|lib| format!("{lib}.lib")));
+        cmd.arg("/link")
+            .arg(format!("/LIBPATH:{}", lib_dir.display()));
+    } else {
+        cmd.arg("-o").arg(&path).arg("-L").arg(lib_dir);
+        cmd.args(libs.iter().map(|lib| format!("-l{lib}")));
+        if target.contains("windows") {
+            cmd.arg("-municode");
+        }
+        add_system_libs(&mut cmd, target);
+    }
+
+    run_linker(&mut cmd, &compiler, bin)?;
+    Ok(path)
+}
+
+/// Links `objects` into the shared library `lib` in `out_dir` and returns its path.
+///
+/// On Windows, the import library is written to `out_dir` as well.
+fn link_shared(
+    config: &cc::Build,
+    target: &str,
+    out_dir: &Path,
+    lib: &str,
+    objects: &[PathBuf],
+) -> Result<PathBuf, Error> {
+    let file_name = if target.contains("windows") {
+        format!("{lib}.dll")
+    } else if target.contains("apple") {
+        format!("lib{lib}.dylib")
+    } else {
+        format!("lib{lib}.so")
+    };
+    let compiler = config.try_get_compiler().map_err(|err| Error::Link {
+        bin: file_name.clone(),
+        message: err.to_string(),
+    })?;
+
+    let mut cmd = compiler.to_command();
+    cmd.args(objects);
+    let path = out_dir.join(&file_name);
+    if compiler.is_like_msvc() {
+        let import_lib = out_dir.join(format!("{lib}.lib"));
+        cmd.arg("/LD").arg(format!("/Fe{}", path.display()));
+        cmd.arg("/link")
+            .arg(format!("/IMPLIB:{}", import_lib.display()));
+    } else {
+        cmd.arg("-o").arg(&path);
+        if target.contains("windows") {
+            let import_lib = out_dir.join(format!("lib{lib}.dll.a"));
+            cmd.arg("-shared")
+                .arg(format!("-Wl,--out-implib,{}", import_lib.display()));
+        } else if target.contains("apple") {
+            cmd.arg("-dynamiclib")
+                .arg(format!("-Wl,-install_name,@rpath/{file_name}"));
+        } else {
+            cmd.arg("-shared").arg(format!("-Wl,-soname,{file_name}"));
+        }
+        add_system_libs(&mut cmd, target);
+    }
+
+    run_linker(&mut cmd, &compiler, &file_name)?;
+    Ok(path)
+}
+
+/// Adds the system libraries Pluto depends on to a GNU-like link command.
+fn add_system_libs(cmd: &mut Command, target: &str) {
+    if target.contains("windows") {
+        // `#pragma comment(lib, ...)` is only honored by MSVC
+        for lib in ["ws2_32", "bcrypt", "user32", "shell32", "gdi32"] {
+            cmd.arg(format!("-l{lib}"));
+        }
+    } else if !target.contains("apple") {
+        cmd.arg("-pthread");
+        // Part of libc on musl, but separate libraries before glibc 2.34
+        if target.contains("linux-gnu") {
+            cmd.arg("-ldl").arg("-lm");
+        }
+    }
+}
+
+fn run_linker(cmd: &mut Command, compiler: &cc::Tool, bin: &str) -> Result<(), Error> {
+    let output = cmd
+        .output()
+        .map_err(|err| Error::io(compiler.path(), err))?;
+    if !output.status.success() {
+        return Err(Error::Link {
+            bin: bin.to_string(),
+            message: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ilp_zero_iterations() {
+        let mut build = Build::new();
+        build.infinite_loop_protection(IlpConfig {
+            max_iterations: 0,
+            ..Default::default()
+        });
+        assert!(matches!(build.validate(), Err(Error::InvalidConfig(_))));
+        build.infinite_loop_protection(IlpConfig::default());
+        assert!(build.validate().is_ok());
+    }
+
+    #[test]
+    fn test_dynamic_with_hooks() {
+        let mut build = Build::new();
+        build.link_kind(LinkKind::Dynamic);
+        assert!(build.validate().is_ok());
+        build.load_hook("host_load_hook");
+        assert!(matches!(build.validate(), Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_vm_dump_whitelist() {
+        let mut build = Build::new();
+        build.vm_dump(VmDumpConfig {
+            whitelist: vec!["OP_CALL".to_string(), "OP_RETURN1".to_string()],
+            ..Default::default()
+        });
+        assert!(build.validate().is_ok());
+        for op in ["OP_CALL); abort(", "OP_", "CALL", "op_call"] {
+            build.vm_dump(VmDumpConfig {
+                whitelist: vec![op.to_string()],
+                ..Default::default()
+            });
+            assert!(
+                matches!(build.validate(), Err(Error::InvalidConfig(_))),
+                "{op}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_symbol_prefix() {
+        let mut build = Build::new();
+        for prefix in ["", "pluto_", "_p1"] {
+            build.symbol_prefix(prefix);
+            assert!(build.validate().is_ok(), "{prefix}");
+        }
+        for prefix in ["1p", "p-", "p ()"] {
+            build.symbol_prefix(prefix);
+            assert!(
+                matches!(build.validate(), Err(Error::InvalidConfig(_))),
+                "{prefix}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_ffi_module() {
+        let path = env::temp_dir().join(format!("pluto-src-ffi-{}.rs", std::process::id()));
+        let ffi_module = |build: &Build| {
+            write_ffi_module(&path, None, &build.defines()).unwrap();
+            fs::read_to_string(&path).unwrap()
+        };
+
+        let mut build = Build::new();
+        let module = ffi_module(&build);
+        assert!(module.contains("pub const LUAI_MAXSTACK: c_int = 1_000_000;\n"));
+        assert!(module.contains("pub fn lua_setcachelen("));
+        assert!(module.contains("pub fn lua_freezetable("));
+        assert!(!module.contains("pluto_set_memory_limit("));
+        assert!(!module.contains("pluto_set_error_style("));
+        assert!(!module.contains("Only available with"));
+
+        (build.set_max_stack_size(2000))
+            .per_state_memory_limit(true)
+            .per_state_error_style(true)
+            .disable_length_cache(true)
+            .disable_table_freezing(true);
+        let module = ffi_module(&build);
+        fs::remove_file(&path).unwrap();
+        assert!(module.contains("pub const LUAI_MAXSTACK: c_int = 2000;\n"));
+        assert!(module.contains("pub fn pluto_set_memory_limit("));
+        assert!(module.contains("pub fn pluto_get_error_style("));
+        assert!(!module.contains("lua_setcachelen("));
+        assert!(!module.contains("lua_erriffrozen("));
+        assert!(!module.contains("Not available with"));
     }
 }
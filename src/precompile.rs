@@ -0,0 +1,285 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{Build, Error};
+
+/// Precompiles Pluto scripts into bytecode at build time.
+///
+/// The scripts are compiled with a `plutoc` built for the host from the same [`Build`]
+/// configuration as the embedded library, so that both agree on the bytecode format.
+#[derive(Clone)]
+pub struct Precompile {
+    out_dir: Option<PathBuf>,
+    target: Option<String>,
+    host: Option<String>,
+    // Configuration of the library that will load the bytecode
+    config: Option<Build>,
+    scripts: Vec<PathBuf>,
+    // Strip debug information
+    strip: bool,
+}
+
+/// The output of [`Precompile::build`].
+pub struct Bytecode {
+    module: PathBuf,
+    chunks: Vec<PathBuf>,
+    scripts: Vec<PathBuf>,
+}
+
+impl Precompile {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Precompile {
+        Precompile {
+            out_dir: env::var_os("OUT_DIR").map(|s| PathBuf::from(s).join("pluto-bytecode")),
+            target: env::var("TARGET").ok(),
+            host: env::var("HOST").ok(),
+            config: None,
+            scripts: Vec::new(),
+            strip: false,
+        }
+    }
+
+    pub fn out_dir<P: AsRef<Path>>(&mut self, path: P) -> &mut Precompile {
+        self.out_dir = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn target(&mut self, target: &str) -> &mut Precompile {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    pub fn host(&mut self, host: &str) -> &mut Precompile {
+        self.host = Some(host.to_string());
+        self
+    }
+
+    /// Sets the configuration of the Pluto library that will load the bytecode.
+    ///
+    /// Defaults to [`Build::new`]. Hooks are ignored, see [`Build::build_cli`].
+    pub fn config(&mut self, build: &Build) -> &mut Precompile {
+        self.config = Some(build.clone());
+        self
+    }
+
+    /// Adds a script to precompile.
+    ///
+    /// The generated constant is named after the file stem, e.g. `INIT` for `init.pluto`.
+    pub fn script<P: AsRef<Path>>(&mut self, path: P) -> &mut Precompile {
+        self.scripts.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Strips debug information (line numbers, local and upvalue names) from the bytecode.
+    pub fn strip(&mut self, strip: bool) -> &mut Precompile {
+        self.strip = strip;
+        self
+    }
+
+    /// Precompiles the scripts, panicking on failure.
+    ///
+    /// See [`Precompile::try_build`] for a non-panicking version.
+    pub fn build(&mut self) -> Bytecode {
+        match self.try_build() {
+            Ok(bytecode) => bytecode,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    /// Precompiles the scripts, returning an error instead of panicking.
+    pub fn try_build(&mut self) -> Result<Bytecode, Error> {
+        self.validate()?;
+        let host = &self.host.as_ref().ok_or(Error::MissingEnv("HOST"))?[..];
+        let out_dir = self.out_dir.as_ref().ok_or(Error::MissingEnv("OUT_DIR"))?;
+        let config = self.config.clone().unwrap_or_else(Build::new);
+
+        // Build the compiler for the host
+        let plutoc = config.build_host_plutoc(host, &out_dir.join("plutoc"))?;
+
+        let chunks_dir = out_dir.join("chunks");
+        fs::create_dir_all(&chunks_dir).map_err(|err| Error::io(&chunks_dir, err))?;
+
+        let mut module = String::from("// Generated by pluto-src\n");
+        let mut names = Vec::with_capacity(self.scripts.len());
+        let mut chunks = Vec::with_capacity(self.scripts.len());
+        for script in &self.scripts {
+            let precompile_error = |message: String| Error::Precompile {
+                script: script.clone(),
+                message,
+            };
+
+            let stem = script.file_stem().unwrap_or_default().to_string_lossy();
+            let name = const_name(&stem);
+            if names.contains(&name) {
+                return Err(precompile_error(format!("duplicate constant {name}")));
+            }
+
+            let chunk = chunks_dir.join(format!("{stem}.out"));
+            let mut cmd = Command::new(&plutoc);
+            if self.strip {
+                cmd.arg("-s");
+            }
+            cmd.arg("-o").arg(&chunk).arg("--").arg(script);
+            let output = cmd.output().map_err(|err| Error::io(&plutoc, err))?;
+            if !output.status.success() {
+                let message = String::from_utf8_lossy(&output.stderr);
+                return Err(precompile_error(message.trim_end().to_string()));
+            }
+
+            module.push_str(&format!(
+                "\n/// Bytecode of `{}`.\npub const {name}: &[u8] = include_bytes!({:?});\n",
+                script.display(),
+                chunk.display().to_string(),
+            ));
+            names.push(name);
+            chunks.push(chunk);
+        }
+
+        let module_path = out_dir.join("bytecode.rs");
+        fs::write(&module_path, module).map_err(|err| Error::io(&module_path, err))?;
+
+        Ok(Bytecode {
+            module: module_path,
+            chunks,
+            scripts: self.scripts.clone(),
+        })
+    }
+
+    /// Rejects configurations whose bytecode can't be loaded on the target.
+    fn validate(&self) -> Result<(), Error> {
+        let target = &self.target.as_ref().ok_or(Error::MissingEnv("TARGET"))?[..];
+        let host = &self.host.as_ref().ok_or(Error::MissingEnv("HOST"))?[..];
+
+        if let Some(true) = self
+            .config
+            .as_ref()
+            .and_then(|config| config.disable_bytecode)
+        {
+            return Err(Error::BytecodeDisabled);
+        }
+        if BytecodeFormat::of(target) != BytecodeFormat::of(host) {
+            return Err(Error::IncompatibleBytecode {
+                target: target.to_string(),
+                host: host.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Bytecode {
+    /// Path to the generated Rust module, to be used with `include!`.
+    pub fn module(&self) -> &Path {
+        &self.module
+    }
+
+    /// Paths to the bytecode files, in the order the scripts were added.
+    pub fn chunks(&self) -> &[PathBuf] {
+        &self.chunks
+    }
+
+    pub fn print_cargo_metadata(&self) {
+        for script in &self.scripts {
+            println!("cargo:rerun-if-changed={}", script.display());
+        }
+    }
+}
+
+/// Properties of a target checked by the header of a bytecode chunk.
+///
+/// `Instruction` is always 32 bits wide and `luaconf.h` leaves `LUA_32BITS` off, so
+/// `lua_Integer` is a `long long` and `lua_Number` a `double`. Both use the same
+/// representation everywhere, which leaves the byte order and the size of `double`.
+#[derive(PartialEq, Eq)]
+struct BytecodeFormat {
+    big_endian: bool,
+    integer_size: usize,
+    number_size: usize,
+}
+
+impl BytecodeFormat {
+    fn of(target: &str) -> BytecodeFormat {
+        let arch = target.split('-').next().unwrap_or_default();
+        let big_endian = match arch {
+            _ if arch.ends_with("el") || arch.ends_with("le") => false,
+            _ if arch.starts_with("powerpc") || arch.starts_with("mips") => true,
+            _ if arch.starts_with("sparc") || arch.starts_with("armeb") => true,
+            _ if arch.starts_with("thumbeb") => true,
+            "aarch64_be" | "bpfeb" | "m68k" | "s390x" => true,
+            _ => false,
+        };
+        // avr-gcc makes `double` an alias of `float`
+        let number_size = match arch {
+            "avr" => 4,
+            _ => 8,
+        };
+        BytecodeFormat {
+            big_endian,
+            integer_size: 8,
+            number_size,
+        }
+    }
+}
+
+/// Converts a file stem into an upper case Rust identifier.
+fn const_name(stem: &str) -> String {
+    let mut name: String = stem
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() => c.to_ascii_uppercase(),
+            _ => '_',
+        })
+        .collect();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name.insert(0, '_');
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn precompile(target: &str, host: &str) -> Precompile {
+        let mut precompile = Precompile::new();
+        precompile.target(target).host(host);
+        precompile
+    }
+
+    #[test]
+    fn test_bytecode_disabled() {
+        let mut config = Build::new();
+        config.disable_bytecode(true);
+        let mut precompile = precompile("x86_64-unknown-linux-gnu", "x86_64-unknown-linux-gnu");
+        let err = precompile.config(&config).validate().err();
+        assert!(matches!(err, Some(Error::BytecodeDisabled)), "{err:?}");
+    }
+
+    #[test]
+    fn test_incompatible_bytecode() {
+        let host = "x86_64-unknown-linux-gnu";
+        for target in [
+            "i686-pc-windows-msvc",
+            "aarch64-apple-darwin",
+            "wasm32-unknown-unknown",
+            "mipsel-unknown-linux-gnu",
+        ] {
+            let err = precompile(target, host).validate().err();
+            assert!(err.is_none(), "{target}: {err:?}");
+        }
+        for target in [
+            "s390x-unknown-linux-gnu",
+            "powerpc64-unknown-linux-gnu",
+            "bpfeb-unknown-none",
+            "avr-unknown-gnu-atmega328",
+        ] {
+            let err = precompile(target, host).validate().err();
+            assert!(
+                matches!(err, Some(Error::IncompatibleBytecode { .. })),
+                "{target}: {err:?}"
+            );
+        }
+    }
+}
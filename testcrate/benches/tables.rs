@@ -0,0 +1,100 @@
+//! Measures table-heavy scripts, to compare builds with and without the table length cache
+//! and table freezing:
+//!
+//! ```sh
+//! cargo bench -p testcrate --bench tables
+//! cargo bench -p testcrate --bench tables --features no-length-cache,no-table-freezing
+//! ```
+//!
+//! For each script, prints the fastest and median run time, and the memory in use
+//! after a full collection while the table it returns is still alive.
+
+use std::ffi::CStr;
+use std::time::{Duration, Instant};
+
+// Links Pluto (and the hooks it may be built with)
+extern crate testcrate;
+
+include!(env!("PLUTO_FFI_MODULE"));
+use ffi::*;
+
+const RUNS: usize = 10;
+
+const SCRIPTS: &[(&str, &str)] = &[
+    (
+        "append",
+        "local t = {} for i = 1, 1000000 do t[#t + 1] = i end return t",
+    ),
+    (
+        "length",
+        "local t = {} for i = 1, 1000 do t[i] = i end
+        local n = 0 for _ = 1, 1000000 do n = n + #t end return t",
+    ),
+    (
+        "insert-remove",
+        "local t = {} for i = 1, 300000 do table.insert(t, i) end
+        for _ = 1, 150000 do table.remove(t) end return t",
+    ),
+    (
+        "records",
+        "local t = {} for i = 1, 200000 do t[i] = {id = i, name = 'n' .. i} end return t",
+    ),
+    (
+        "hash",
+        "local t = {} for i = 1, 300000 do t['k' .. i] = i end
+        local s = 0 for _, v in pairs(t) do s = s + v end return t",
+    ),
+];
+
+fn main() {
+    let state = |disabled: bool| if disabled { "off" } else { "on" };
+    println!(
+        "length cache: {}, table freezing: {}",
+        state(cfg!(feature = "no-length-cache")),
+        state(cfg!(feature = "no-table-freezing")),
+    );
+    for (name, code) in SCRIPTS {
+        let mut times = Vec::with_capacity(RUNS);
+        let mut memory = 0;
+        for _ in 0..RUNS {
+            let (time, bytes) = unsafe { measure(code) };
+            times.push(time);
+            memory = bytes;
+        }
+        times.sort();
+        println!(
+            "{name:<14} min {:>9.2?}  median {:>9.2?}  memory {:>7} KiB",
+            times[0],
+            times[RUNS / 2],
+            memory / 1024,
+        );
+    }
+}
+
+/// Runs `code` in a new state, returning the run time and the bytes in use afterwards.
+unsafe fn measure(code: &str) -> (Duration, usize) {
+    let state = luaL_newstate();
+    assert!(!state.is_null());
+    luaL_openlibs(state);
+    let status = luaL_loadbuffer(state, code.as_ptr().cast(), code.len(), c"=bench".as_ptr());
+    assert_eq!(status, LUA_OK, "{}", error_message(state));
+
+    let start = Instant::now();
+    let status = lua_pcall(state, 0, 1, 0);
+    let time = start.elapsed();
+    assert_eq!(status, LUA_OK, "{}", error_message(state));
+
+    lua_gc(state, LUA_GCCOLLECT);
+    let kbytes = lua_gc(state, LUA_GCCOUNT) as usize;
+    let bytes = kbytes * 1024 + lua_gc(state, LUA_GCCOUNTB) as usize;
+    lua_close(state);
+    (time, bytes)
+}
+
+unsafe fn error_message(state: *mut lua_State) -> String {
+    let message = lua_tostring(state, -1);
+    if message.is_null() {
+        return "(error object is not a string)".to_string();
+    }
+    CStr::from_ptr(message).to_string_lossy().into_owned()
+}
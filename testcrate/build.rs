@@ -1,5 +1,151 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use pluto_src::{
+    Dialect, IlpConfig, IlpMode, KeywordSet, LinkKind, PlutoLib, SandboxProfile, VmDumpConfig,
+};
+
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
-    let artifacts = pluto_src::Build::new().build();
+    let mut build = pluto_src::Build::new();
+    if cfg!(feature = "minimal-libs") {
+        build.preloaded_libs(&[PlutoLib::Json, PlutoLib::Crypto]);
+    }
+    if cfg!(feature = "sandbox-restricted") {
+        build.sandbox(SandboxProfile::Restricted);
+    }
+    if cfg!(feature = "sandbox-untrusted") {
+        build.sandbox(SandboxProfile::Untrusted);
+    }
+    let ilp_mode = if cfg!(feature = "ilp-error") {
+        Some(IlpMode::Error)
+    } else if cfg!(feature = "ilp-silent") {
+        Some(IlpMode::SilentBreak)
+    } else if cfg!(feature = "ilp-hook") {
+        Some(IlpMode::Hook("testcrate_ilp_hook".to_string()))
+    } else {
+        None
+    };
+    if let Some(mode) = ilp_mode {
+        build.infinite_loop_protection(IlpConfig {
+            max_iterations: 10_000,
+            mode,
+            ..Default::default()
+        });
+    }
+    if cfg!(feature = "etl") {
+        // Long enough for the other tests of a state, e.g. with `memory-limit`
+        build
+            .execution_time_limit(Duration::from_secs(1))
+            .execution_time_limit_hook("testcrate_etl_timesup");
+    }
+    if cfg!(feature = "no-length-cache") {
+        build.disable_length_cache(true);
+    }
+    if cfg!(feature = "no-table-freezing") {
+        build.disable_table_freezing(true);
+    }
+    if cfg!(feature = "no-pluto-stdlib-code") {
+        build.load_pluto_stdlib_code(false);
+    }
+    if cfg!(feature = "prelude") {
+        build.prelude("scripts/prelude.pluto");
+    }
+    if cfg!(feature = "short-errors") {
+        build.short_errors(true);
+    }
+    if cfg!(feature = "colored-errors") {
+        build.colored_errors(true);
+    }
+    if cfg!(feature = "per-state-error-style") {
+        build.per_state_error_style(true);
+    }
+    if cfg!(feature = "vm-dump") {
+        build.vm_dump(VmDumpConfig {
+            whitelist: vec!["OP_RETURN1".to_string()],
+            condition_symbol: Some("testcrate_vm_dump_cond".to_string()),
+        });
+    }
+    if cfg!(feature = "memory-limit") {
+        build.memory_limit(16_000_000).per_state_memory_limit(true);
+    }
+    if cfg!(feature = "load-hooks") {
+        build
+            .load_hook("testcrate_load_hook")
+            .loadfile_hook("testcrate_loadfile_hook")
+            .loadclib_hook("testcrate_loadclib_hook");
+    }
+    if cfg!(feature = "file-hooks") {
+        build
+            .read_file_hook("testcrate_read_file_hook")
+            .write_file_hook("testcrate_write_file_hook");
+    }
+    if cfg!(feature = "http-hook") {
+        build.http_request_hook("testcrate_http_request_hook");
+    }
+    if cfg!(feature = "ffi-hook") {
+        build.ffi_call_hook("testcrate_ffi_call_hook");
+    }
+    if cfg!(feature = "cli") {
+        build.build_cli(true);
+    }
+    if cfg!(feature = "dynamic") {
+        build.link_kind(LinkKind::Dynamic);
+    }
+    if cfg!(feature = "symbol-prefix") {
+        build.symbol_prefix("pluto_");
+    }
+    let mut dialect = Dialect::default();
+    if cfg!(feature = "compatible-keywords") {
+        dialect.compatible_keywords = KeywordSet::ALL;
+    }
+    if cfg!(feature = "optional-keywords") {
+        dialect.use_let = true;
+        dialect.use_const = true;
+        dialect.use_global = true;
+        dialect.paranoid = true;
+    }
+    build.dialect(dialect);
+    if cfg!(feature = "precompile") {
+        let bytecode = pluto_src::Precompile::new()
+            .config(&build)
+            .script("scripts/greet.pluto")
+            .strip(true)
+            .build();
+        bytecode.print_cargo_metadata();
+        println!(
+            "cargo:rustc-env=PLUTO_BYTECODE={}",
+            bytecode.module().display()
+        );
+    }
+    if cfg!(feature = "check") {
+        pluto_src::check_scripts(&["scripts/**/*.pluto"]);
+        let report = pluto_src::ScriptCheck::new()
+            .config(&build)
+            .pattern("lint/*.pluto")
+            .try_check()
+            .unwrap();
+        let mut lines = Vec::new();
+        lines.extend(report.errors().iter().map(|e| format!("error: {e}")));
+        lines.extend(report.warnings().iter().map(|w| format!("warning: {w}")));
+        let path = Path::new(&env::var("OUT_DIR").unwrap()).join("check-report.txt");
+        fs::write(&path, lines.join("\n")).unwrap();
+        println!("cargo:rustc-env=PLUTO_CHECK_REPORT={}", path.display());
+    }
+    let artifacts = build.build();
     artifacts.print_cargo_metadata();
+    println!(
+        "cargo:rustc-env=PLUTO_INCLUDE_DIR={}",
+        artifacts.include_dir().display()
+    );
+    println!(
+        "cargo:rustc-env=PLUTO_FFI_MODULE={}",
+        artifacts.ffi_module().display()
+    );
+    if let (Some(pluto), Some(plutoc)) = (artifacts.pluto_bin(), artifacts.plutoc_bin()) {
+        println!("cargo:rustc-env=PLUTO_BIN={}", pluto.display());
+        println!("cargo:rustc-env=PLUTOC_BIN={}", plutoc.display());
+    }
 }
@@ -1,54 +1,159 @@
-use std::os::raw::{c_char, c_int, c_long, c_void};
+#[allow(unused_imports)] // Depending on the enabled features
+use std::os::raw::{c_char, c_int, c_void};
 
+#[cfg(not(feature = "symbol-prefix"))]
+use pluto_src::ffi::*;
+
+// Bindings linked to the prefixed symbols
+#[cfg(feature = "symbol-prefix")]
+include!(env!("PLUTO_FFI_MODULE"));
+#[cfg(feature = "symbol-prefix")]
+use ffi::*;
+
+#[cfg(feature = "ffi-hook")]
 extern "C" {
-    pub fn luaL_newstate() -> *mut c_void;
-    pub fn luaL_openlibs(state: *mut c_void);
-    pub fn lua_getfield(state: *mut c_void, index: c_int, k: *const c_char);
-    pub fn lua_tolstring(state: *mut c_void, index: c_int, len: *mut c_long) -> *const c_char;
-    pub fn luaL_loadstring(state: *mut c_void, s: *const c_char) -> c_int;
-    pub fn luaL_error(state: *mut c_void, fmt: *const c_char, ...) -> c_int;
+    fn abs(i: c_int) -> c_int;
+}
 
-    pub fn lua_pushcclosure(
-        state: *mut c_void,
-        f: unsafe extern "C-unwind" fn(state: *mut c_void) -> c_int,
-        n: c_int,
-    );
+#[cfg(feature = "ilp-hook")]
+pub static ILP_HOOK_CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+#[cfg(feature = "ilp-hook")]
+#[no_mangle]
+extern "C-unwind" fn testcrate_ilp_hook(_state: *mut c_void) {
+    ILP_HOOK_CALLS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+}
+
+#[cfg(feature = "etl")]
+#[no_mangle]
+unsafe extern "C-unwind" fn testcrate_etl_timesup(state: *mut c_void) {
+    luaL_error(state.cast(), c"timeout!".as_ptr());
+}
+
+/// Traces the states whose extra space starts with 1.
+#[cfg(feature = "vm-dump")]
+#[no_mangle]
+unsafe extern "C" fn testcrate_vm_dump_cond(state: *mut c_void) -> bool {
+    *lua_getextraspace(state.cast()).cast::<u8>() == 1
+}
+
+/// Returns `true` if the (nullable) C string doesn't contain "forbidden".
+#[cfg(any(feature = "load-hooks", feature = "file-hooks", feature = "http-hook"))]
+unsafe fn is_allowed(s: *const c_char) -> bool {
+    s.is_null()
+        || !std::ffi::CStr::from_ptr(s)
+            .to_bytes()
+            .windows(9)
+            .any(|w| w == b"forbidden")
+}
+
+#[cfg(feature = "load-hooks")]
+#[no_mangle]
+unsafe extern "C" fn testcrate_load_hook(_state: *mut c_void, code: *const c_char) -> bool {
+    is_allowed(code)
+}
+
+#[cfg(feature = "load-hooks")]
+#[no_mangle]
+unsafe extern "C" fn testcrate_loadfile_hook(_: *mut c_void, filename: *const c_char) -> bool {
+    is_allowed(filename)
+}
 
-    pub fn lua_pcallk(
-        state: *mut c_void,
-        nargs: c_int,
-        nresults: c_int,
-        errfunc: c_int,
-        ctx: isize,
-        k: *const c_void,
-    ) -> c_int;
+#[cfg(feature = "load-hooks")]
+#[no_mangle]
+extern "C" fn testcrate_loadclib_hook(_state: *mut c_void, _path: *const c_char) -> bool {
+    false
+}
+
+#[cfg(feature = "file-hooks")]
+#[no_mangle]
+unsafe extern "C" fn testcrate_read_file_hook(_state: *mut c_void, path: *const c_char) -> bool {
+    is_allowed(path)
+}
+
+#[cfg(feature = "file-hooks")]
+#[no_mangle]
+extern "C" fn testcrate_write_file_hook(_state: *mut c_void, _path: *const c_char) -> bool {
+    false
+}
+
+/// Serves a canned response for `stub://` URLs, so no network access is needed.
+#[cfg(feature = "http-hook")]
+#[no_mangle]
+unsafe extern "C" fn testcrate_http_request_hook(state: *mut c_void, url: *const c_char) -> bool {
+    if std::ffi::CStr::from_ptr(url)
+        .to_bytes()
+        .starts_with(b"stub://")
+    {
+        lua_pushstring(state.cast(), c"stub body".as_ptr());
+        lua_pushinteger(state.cast(), 200);
+    }
+    is_allowed(url)
+}
 
-    pub fn lua_getglobal(state: *mut c_void, k: *const c_char) -> c_int;
+#[cfg(feature = "ffi-hook")]
+#[no_mangle]
+extern "C-unwind" fn testcrate_ffi_call_hook(_state: *mut c_void, addr: *mut c_void) -> bool {
+    addr.cast_const() == abs as *const c_void
 }
 
-pub unsafe fn lua_pcall(
-    state: *mut c_void,
-    nargs: c_int,
-    nresults: c_int,
-    errfunc: c_int,
-) -> c_int {
-    lua_pcallk(state, nargs, nresults, errfunc, 0, std::ptr::null())
+/// Runs `code` in a new state with the standard libraries opened.
+///
+/// Returns the first result (or the error) converted to a string.
+pub fn run(code: impl AsRef<[u8]>) -> Result<String, String> {
+    unsafe {
+        let state = luaL_newstate();
+        assert!(!state.is_null());
+        luaL_openlibs(state);
+        let result = eval(state, code);
+        lua_close(state);
+        result
+    }
+}
+
+/// Runs `code` in the given state.
+///
+/// Returns the first result (or the error) converted to a string.
+///
+/// # Safety
+///
+/// `state` must be a valid Lua state.
+pub unsafe fn eval(state: *mut lua_State, code: impl AsRef<[u8]>) -> Result<String, String> {
+    use std::slice;
+
+    let code = code.as_ref();
+    let mut status = luaL_loadbuffer(state, code.as_ptr().cast(), code.len(), c"=run".as_ptr());
+    if status == LUA_OK {
+        status = lua_pcall(state, 0, 1, 0);
+    }
+    let result = {
+        let mut len = 0;
+        let ptr = luaL_tolstring(state, -1, &mut len);
+        let bytes = slice::from_raw_parts(ptr as *const u8, len);
+        String::from_utf8_lossy(bytes).into_owned()
+    };
+    lua_settop(state, 0);
+
+    match status {
+        LUA_OK => Ok(result),
+        _ => Err(result),
+    }
 }
 
 #[test]
 fn test_lua() {
-    use std::{ptr, slice};
+    use std::slice;
     unsafe {
         let state = luaL_newstate();
-        assert!(state != ptr::null_mut());
+        assert!(!state.is_null());
 
         luaL_openlibs(state);
 
         let version = {
-            lua_getglobal(state, "_VERSION\0".as_ptr().cast());
-            let mut len: c_long = 0;
+            lua_getglobal(state, c"_VERSION".as_ptr());
+            let mut len = 0;
             let version_ptr = lua_tolstring(state, -1, &mut len);
-            slice::from_raw_parts(version_ptr as *const u8, len as usize)
+            slice::from_raw_parts(version_ptr as *const u8, len)
         };
 
         assert_eq!(version, "Lua 5.4".as_bytes());
@@ -57,24 +162,858 @@ fn test_lua() {
 
 #[test]
 fn test_exceptions() {
-    use std::{ptr, slice, str};
+    use std::{slice, str};
     unsafe {
         let state = luaL_newstate();
-        assert!(state != ptr::null_mut());
+        assert!(!state.is_null());
 
-        unsafe extern "C-unwind" fn it_panics(state: *mut c_void) -> c_int {
-            luaL_error(state, "exception!\0".as_ptr().cast())
+        unsafe extern "C-unwind" fn it_panics(state: *mut lua_State) -> c_int {
+            luaL_error(state, c"exception!".as_ptr())
         }
 
-        lua_pushcclosure(state, it_panics, 0);
+        lua_pushcfunction(state, it_panics);
         let result = lua_pcall(state, 0, 0, 0);
-        assert_eq!(result, 2); // LUA_ERRRUN
+        assert_eq!(result, LUA_ERRRUN);
         let s = {
-            let mut len: c_long = 0;
+            let mut len = 0;
             let version_ptr = lua_tolstring(state, -1, &mut len);
-            let s = slice::from_raw_parts(version_ptr as *const u8, len as usize);
+            let s = slice::from_raw_parts(version_ptr as *const u8, len);
             str::from_utf8(s).unwrap()
         };
         assert_eq!(s, "exception!");
     }
 }
+
+#[cfg(not(feature = "minimal-libs"))]
+#[test]
+fn test_preloaded_libs() {
+    let preloaded = run("return package.preload.json ~= nil and package.preload.socket ~= nil");
+    assert_eq!(preloaded.as_deref(), Ok("true"));
+}
+
+#[cfg(feature = "minimal-libs")]
+#[test]
+fn test_minimal_libs() {
+    assert_eq!(
+        run("return require('json').encode({1})").as_deref(),
+        Ok("[1]")
+    );
+    assert_eq!(
+        run("return require('crypto').md5('')").as_deref(),
+        Ok("d41d8cd98f00b204e9800998ecf8427e")
+    );
+    for lib in ["socket", "http", "ffi", "bigint", "*"] {
+        let code = format!("return package.preload['{lib}'] == nil");
+        assert_eq!(run(&code).as_deref(), Ok("true"), "{lib}");
+    }
+    // Compile-time library lookups in the parser only know about preloaded libraries
+    assert!(run("return canvas.new(1, 1)").is_err());
+}
+
+#[cfg(not(any(feature = "sandbox-restricted", feature = "sandbox-untrusted")))]
+#[test]
+fn test_no_sandbox() {
+    let code = r#"return io ~= nil and os.execute ~= nil and package.loadlib ~= nil
+        and load(string.dump(function() end)) ~= nil and debug ~= nil"#;
+    assert_eq!(run(code).as_deref(), Ok("true"));
+}
+
+#[cfg(any(feature = "sandbox-restricted", feature = "sandbox-untrusted"))]
+#[test]
+fn test_sandbox_restricted() {
+    assert_eq!(run("return io").as_deref(), Ok("nil"));
+    assert_eq!(run("return os.execute").as_deref(), Ok("nil"));
+    // No C module searchers and no `package.loadlib`
+    assert_eq!(run("return package.loadlib").as_deref(), Ok("nil"));
+    assert_eq!(run("return #package.searchers").as_deref(), Ok("2"));
+    let err = run("return require('http').request('http://127.0.0.1:1/')").unwrap_err();
+    assert!(
+        err.contains("disallowed by content moderation policy"),
+        "{err}"
+    );
+}
+
+#[cfg(feature = "sandbox-restricted")]
+#[test]
+fn test_sandbox_restricted_allows_bytecode() {
+    let code = "return load(string.dump(function() return 1 end))()";
+    assert_eq!(run(code).as_deref(), Ok("1"));
+}
+
+#[cfg(feature = "sandbox-untrusted")]
+#[test]
+fn test_sandbox_untrusted() {
+    let code = "return load(string.dump(function() return 1 end))";
+    assert_eq!(run(code).as_deref(), Ok("nil"));
+    assert_eq!(run("return debug").as_deref(), Ok("nil"));
+    let code = r#"local ok, err = pcall(|| -> pluto_new exception("oops")) return tostring(err)"#;
+    assert_eq!(run(code).as_deref(), Ok("run:1: oops"));
+    let err = run("require('assert').equal(1, 2)").unwrap_err();
+    assert!(err.contains("run:1"), "{err}");
+    assert!(run("return load(function() return nil end)").is_err());
+    let err = run("local t = {} for i = 1, 1e9 do t[i] = i end").unwrap_err();
+    assert!(err.contains("not enough memory"), "{err}");
+}
+
+#[test]
+fn test_table_length() {
+    let code = r#"
+        local t = {1, 2, 3}
+        local n1 = #t
+        t[#t + 1] = 4
+        local n2 = #t
+        rawset(t, 5, 5)
+        local n3 = #t
+        table.remove(t)
+        t[4] = nil
+        return n1 .. n2 .. n3 .. #t
+    "#;
+    assert_eq!(run(code).as_deref(), Ok("3453"));
+}
+
+#[cfg(not(feature = "no-table-freezing"))]
+#[test]
+fn test_table_freezing() {
+    let err = run("local t = table.freeze({}) t.x = 1").unwrap_err();
+    assert!(err.contains("attempt to modify frozen table"), "{err}");
+}
+
+#[cfg(feature = "no-table-freezing")]
+#[test]
+fn test_table_freezing_disabled() {
+    let code = "return table.freeze == nil and table.isfrozen == nil";
+    assert_eq!(run(code).as_deref(), Ok("true"));
+    assert_eq!(run("local t = {} t.x = 1 return t.x").as_deref(), Ok("1"));
+}
+
+#[cfg(not(any(feature = "no-pluto-stdlib-code", feature = "prelude")))]
+#[test]
+fn test_pluto_stdlib_code() {
+    let code = r#"
+        local ok, err = pcall(|| -> pluto_new exception("oops"))
+        return tostring(instanceof(err, exception)) .. err.what .. table.min({3, 1, 2})
+    "#;
+    assert_eq!(run(code).as_deref(), Ok("trueoops1"));
+}
+
+#[cfg(feature = "no-pluto-stdlib-code")]
+#[test]
+fn test_no_pluto_stdlib_code() {
+    let code = "return table.min == nil and table.max == nil";
+    assert_eq!(run(code).as_deref(), Ok("true"));
+    // The first table literal creates the default metatable of tables
+    assert_eq!(run("return rawlen({})").as_deref(), Ok("0"));
+    #[cfg(not(feature = "prelude"))]
+    assert_eq!(run("return exception").as_deref(), Ok("nil"));
+}
+
+#[cfg(feature = "prelude")]
+#[test]
+fn test_prelude() {
+    let code = r#"return greet("prelude")"#;
+    assert_eq!(run(code).as_deref(), Ok("hello prelude"));
+    assert_eq!(run("return exception or instanceof").as_deref(), Ok("nil"));
+}
+
+#[cfg(not(feature = "per-state-error-style"))]
+#[test]
+fn test_error_style() {
+    let err = run("local x = = 1").unwrap_err();
+    assert!(err.contains("unexpected symbol near '='"), "{err}");
+    assert_eq!(err.contains('\n'), !cfg!(feature = "short-errors"), "{err}");
+    assert_eq!(
+        err.contains('\x1b'),
+        cfg!(feature = "colored-errors"),
+        "{err}"
+    );
+}
+
+#[cfg(feature = "per-state-error-style")]
+#[test]
+fn test_per_state_error_style() {
+    unsafe {
+        let state = luaL_newstate();
+        let style = pluto_get_error_style(state);
+        let short = cfg!(feature = "short-errors");
+        assert_eq!(style & PLUTO_ERRSTYLE_SHORT != 0, short);
+        assert_eq!(
+            style & PLUTO_ERRSTYLE_COLOR != 0,
+            cfg!(feature = "colored-errors")
+        );
+
+        for style in 0..=(PLUTO_ERRSTYLE_SHORT | PLUTO_ERRSTYLE_COLOR) {
+            pluto_set_error_style(state, style);
+            assert_eq!(pluto_get_error_style(state), style);
+            let err = eval(state, "local x = = 1").unwrap_err();
+            assert!(err.contains("unexpected symbol near '='"), "{err}");
+            assert_eq!(
+                err.contains('\n'),
+                style & PLUTO_ERRSTYLE_SHORT == 0,
+                "{err}"
+            );
+            assert_eq!(
+                err.contains('\x1b'),
+                style & PLUTO_ERRSTYLE_COLOR != 0,
+                "{err}"
+            );
+        }
+
+        // Escape sequences in the code snippet are kept without colors
+        pluto_set_error_style(state, 0);
+        let err = eval(state, "local x = = '\x1b[1m'").unwrap_err();
+        assert!(err.contains("'\x1b[1m'"), "{err}");
+        lua_close(state);
+    }
+}
+
+#[test]
+fn test_sandbox_profile_defines() {
+    use pluto_src::SandboxProfile;
+
+    let restricted = SandboxProfile::Restricted.defines();
+    let untrusted = SandboxProfile::Untrusted.defines();
+    for define in &restricted {
+        assert!(untrusted.contains(define), "{define:?}");
+    }
+    let memory_limit = (
+        "PLUTO_MEMORY_LIMIT".to_string(),
+        Some("64000000".to_string()),
+    );
+    assert!(untrusted.contains(&memory_limit));
+    assert!(!restricted.contains(&memory_limit));
+}
+
+#[test]
+fn test_disable_ffi_defines() {
+    let no_ffi = ("PLUTO_NO_FFILIB".to_string(), None);
+    let defines = pluto_src::Build::new().disable_ffi(true).defines();
+    assert!(defines.contains(&no_ffi));
+    assert!(!defines.iter().any(|(name, _)| name == "PLUTO_NO_BINARIES"));
+    let defines = pluto_src::Build::new()
+        .disable_ffi(true)
+        .disable_ffi(false)
+        .defines();
+    assert!(!defines.contains(&no_ffi));
+}
+
+#[test]
+fn test_dialect_defines() {
+    use pluto_src::{Dialect, Keyword, KeywordSet};
+
+    let keywords: KeywordSet = [Keyword::Class, Keyword::Switch].into_iter().collect();
+    assert_eq!(
+        keywords,
+        KeywordSet::NONE.with(Keyword::Switch).with(Keyword::Class)
+    );
+    assert_eq!(KeywordSet::ALL.iter().count(), Keyword::ALL.len());
+    assert!(!KeywordSet::ALL.without(Keyword::Try).contains(Keyword::Try));
+
+    let dialect = Dialect {
+        compatible_keywords: keywords,
+        use_global: true,
+        ..Default::default()
+    };
+    let defines = pluto_src::Build::new().dialect(dialect).defines();
+    let names: Vec<&str> = (defines.iter()).map(|(name, _)| &name[..]).collect();
+    assert_eq!(
+        names,
+        [
+            "PLUTO_COMPATIBLE_SWITCH",
+            "PLUTO_COMPATIBLE_CLASS",
+            "PLUTO_USE_GLOBAL"
+        ]
+    );
+}
+
+#[test]
+fn test_vm_dump_config() {
+    use pluto_src::{Build, VmDumpConfig};
+
+    let config = VmDumpConfig {
+        whitelist: vec!["OP_CALL".to_string(), "OP_RETURN".to_string()],
+        condition_symbol: Some("trace_state".to_string()),
+    };
+    let defines = Build::new().vm_dump(config).defines();
+    assert_eq!(
+        defines,
+        [
+            ("PLUTO_VMDUMP".to_string(), None),
+            ("PLUTO_VMDUMP_WHITELIST".to_string(), None),
+            (
+                "vmDumpAllow".to_string(),
+                Some("OP_CALL, OP_RETURN".to_string())
+            ),
+            (
+                "PLUTO_VMDUMP_COND".to_string(),
+                Some("trace_state".to_string())
+            ),
+        ]
+    );
+}
+
+#[cfg(not(feature = "compatible-keywords"))]
+#[test]
+fn test_pluto_keywords() {
+    let code = "local x = 1 switch x do case 1: return 'one' end";
+    assert_eq!(run(code).as_deref(), Ok("one"));
+    assert!(run("return tostring(class)").is_err());
+    // Names that are obviously not keywords are still valid
+    assert_eq!(run("local class = 1 return class").as_deref(), Ok("1"));
+}
+
+#[cfg(feature = "compatible-keywords")]
+#[test]
+fn test_compatible_keywords() {
+    for name in pluto_src::Keyword::ALL.iter().map(|k| k.name()) {
+        let code = format!("return tostring({name})");
+        assert_eq!(run(&code).as_deref(), Ok("nil"), "{name}");
+        let code = format!("local t = {{{name} = 1}} local {name} = t.{name} return {name} + 1");
+        assert_eq!(run(&code).as_deref(), Ok("2"), "{name}");
+    }
+
+    let err = run("local x = 1 switch x do case 1: return 'one' end").unwrap_err();
+    assert!(err.contains("syntax error"), "{err}");
+    let code = "local x = 1 pluto_switch x do case 1: return 'one' end";
+    assert_eq!(run(code).as_deref(), Ok("one"));
+    let code = "pluto_use switch\nlocal x = 1 switch x do case 1: return 'one' end";
+    assert_eq!(run(code).as_deref(), Ok("one"));
+}
+
+#[cfg(not(feature = "optional-keywords"))]
+#[test]
+fn test_optional_keywords_disabled() {
+    for code in [
+        "let x = 1 return x",
+        "const x = 1 return x",
+        "global g = 1 return g",
+    ] {
+        assert!(run(code).is_err(), "{code}");
+    }
+}
+
+#[cfg(feature = "optional-keywords")]
+#[test]
+fn test_optional_keywords() {
+    assert_eq!(run("let x = 1 return x").as_deref(), Ok("1"));
+    assert_eq!(run("const x = 2 return x").as_deref(), Ok("2"));
+    assert_eq!(run("global g = 3 return g").as_deref(), Ok("3"));
+    // A keyword followed by `(` is taken as a name
+    let err = run("return class(1)").unwrap_err();
+    assert!(err.contains("global 'class'"), "{err}");
+}
+
+#[cfg(feature = "ilp-error")]
+#[test]
+fn test_ilp_error() {
+    let err = run("while true do end").unwrap_err();
+    assert!(err.contains("infinite loop detected"), "{err}");
+}
+
+#[cfg(feature = "ilp-silent")]
+#[test]
+fn test_ilp_silent_break() {
+    assert_eq!(
+        run("while true do end return 'done'").as_deref(),
+        Ok("done")
+    );
+}
+
+#[cfg(feature = "ilp-hook")]
+#[test]
+fn test_ilp_hook() {
+    use std::sync::atomic::Ordering;
+
+    assert_eq!(
+        run("while true do end return 'done'").as_deref(),
+        Ok("done")
+    );
+    assert!(ILP_HOOK_CALLS.load(Ordering::Relaxed) > 0);
+}
+
+#[cfg(feature = "etl")]
+#[test]
+fn test_etl() {
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+    assert_eq!(run("while true do end").unwrap_err(), "timeout!");
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_etl_defines() {
+    use std::time::Duration;
+
+    let nanos = |limit| {
+        let defines = pluto_src::Build::new()
+            .execution_time_limit(limit)
+            .defines();
+        let (_, value) = defines
+            .into_iter()
+            .find(|(name, _)| name == "PLUTO_ETL_NANOS")
+            .unwrap();
+        value.unwrap().parse::<i64>().unwrap()
+    };
+    assert_eq!(nanos(Duration::from_millis(100)), 100_000_000);
+    // Adding the limit to the current time must not overflow
+    let max = nanos(Duration::MAX);
+    assert!(max.checked_add(i64::MAX / 2).is_some());
+    assert!(max > 100 * 365 * 24 * 3600 * 1_000_000_000);
+}
+
+#[cfg(feature = "vm-dump")]
+#[test]
+fn test_vm_dump() {
+    use std::process::Command;
+
+    // The dump is written to the C stdout, so it's captured by running this test in a child process
+    if std::env::var_os("TESTCRATE_VM_DUMP").is_some() {
+        for (traced, n) in [(false, 10), (true, 41)] {
+            unsafe {
+                let state = luaL_newstate();
+                *lua_getextraspace(state).cast::<u8>() = traced as u8;
+                let code = format!("local function inc(x) return x + 1 end return inc({n}) * 1");
+                assert_eq!(eval(state, code), Ok((n + 1).to_string()));
+                lua_close(state);
+            }
+        }
+        return;
+    }
+
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args(["test_vm_dump", "--exact", "--nocapture"])
+        .env("TESTCRATE_VM_DUMP", "1")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{stdout}");
+    // The trace is interleaved with the test harness output
+    let trace: Vec<&str> = (stdout.lines())
+        .filter_map(|line| Some(&line[line.find("RETURN1 ")?..]))
+        .collect();
+    assert_eq!(trace.len(), 1, "{stdout}");
+    assert!(trace[0].ends_with("; return 42"), "{stdout}");
+}
+
+#[cfg(feature = "memory-limit")]
+#[test]
+fn test_memory_limit() {
+    let alloc_8mb = "local s = string.rep('x', 8000000) return #s";
+    assert_eq!(run(alloc_8mb).as_deref(), Ok("8000000"));
+    let err = run("return #string.rep('x', 32000000)").unwrap_err();
+    assert!(err.contains("not enough memory"), "{err}");
+
+    unsafe {
+        let state = luaL_newstate();
+        luaL_openlibs(state);
+        pluto_set_memory_limit(state, 4_000_000);
+        let err = eval(state, alloc_8mb).unwrap_err();
+        assert!(err.contains("not enough memory"), "{err}");
+        pluto_set_memory_limit(state, 64_000_000);
+        assert_eq!(eval(state, alloc_8mb).as_deref(), Ok("8000000"));
+        lua_close(state);
+    }
+}
+
+#[cfg(feature = "load-hooks")]
+#[test]
+fn test_load_hooks() {
+    assert_eq!(run("return load('return 1')()").as_deref(), Ok("1"));
+    let err = run("return load('return \"forbidden\"')").unwrap_err();
+    assert!(
+        err.contains("disallowed by content moderation policy"),
+        "{err}"
+    );
+
+    let code = "return select(2, loadfile('forbidden.pluto'))";
+    assert_eq!(
+        run(code).as_deref(),
+        Ok("disallowed by content moderation policy")
+    );
+    let code = "return select(2, loadfile('missing.pluto'))";
+    assert!(run(code).unwrap().contains("cannot open"));
+
+    let err = run("return package.loadlib('libmissing.so', 'f')").unwrap_err();
+    assert!(
+        err.contains("disallowed by content moderation policy"),
+        "{err}"
+    );
+}
+
+#[cfg(feature = "file-hooks")]
+#[test]
+fn test_file_hooks() {
+    let code = "local f <close> = io.open('Cargo.toml') return f:read('l')";
+    assert_eq!(run(code).as_deref(), Ok("[package]"));
+    assert_eq!(run("return io.exists('Cargo.toml')").as_deref(), Ok("true"));
+
+    for code in [
+        "io.open('forbidden.txt')",
+        "io.exists('forbidden.txt')",
+        "io.open('out.txt', 'w')",
+        "io.makedir('out')",
+    ] {
+        let err = run(code).unwrap_err();
+        assert!(
+            err.contains("disallowed by content moderation policy"),
+            "{code}: {err}"
+        );
+    }
+}
+
+#[cfg(feature = "http-hook")]
+#[test]
+fn test_http_request_hook() {
+    let code =
+        "local body, status = require('http').request('stub://example/') return body .. ' ' .. status";
+    assert_eq!(run(code).as_deref(), Ok("stub body 200"));
+
+    for url in ["http://127.0.0.1:1/forbidden", "stub://forbidden/"] {
+        let err = run(format!("return require('http').request('{url}')")).unwrap_err();
+        assert!(
+            err.contains("disallowed by content moderation policy"),
+            "{url}: {err}"
+        );
+    }
+}
+
+#[cfg(all(feature = "ffi-hook", target_os = "linux"))]
+#[test]
+fn test_ffi_call_hook() {
+    let code =
+        "local libc = require('ffi').open('libc.so.6') return libc:wrap('i32', 'abs', 'i32')(-5)";
+    assert_eq!(run(code).as_deref(), Ok("5"));
+
+    let code =
+        "local libc = require('ffi').open('libc.so.6') return libc:wrap('i64', 'labs', 'i64')(-5)";
+    let err = run(code).unwrap_err();
+    assert!(
+        err.contains("disallowed by content moderation policy"),
+        "{err}"
+    );
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn test_cli() {
+    use std::process::Command;
+
+    let output = Command::new(env!("PLUTO_BIN"))
+        .args(["-e", "print(1 + 1)"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+
+    let dir = std::env::temp_dir().join(format!("testcrate-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (script, bytecode) = (dir.join("script.pluto"), dir.join("script.out"));
+    std::fs::write(&script, "print('compiled')").unwrap();
+    let status = Command::new(env!("PLUTOC_BIN"))
+        .arg("-o")
+        .args([&bytecode, &script])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let output = Command::new(env!("PLUTO_BIN"))
+        .arg(&bytecode)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "compiled\n");
+}
+
+#[cfg(all(test, feature = "precompile"))]
+mod bytecode {
+    include!(env!("PLUTO_BYTECODE"));
+}
+
+#[cfg(feature = "precompile")]
+#[test]
+fn test_precompile() {
+    assert!(bytecode::GREET.starts_with(b"\x1bLua"));
+    assert_eq!(run(bytecode::GREET).as_deref(), Ok("hello bytecode"));
+}
+
+#[cfg(feature = "check")]
+#[test]
+fn test_check_scripts() {
+    let report = include_str!(env!("PLUTO_CHECK_REPORT"));
+    assert!(
+        report.contains("error: lint/broken.pluto:3: unexpected symbol near '<eof>'"),
+        "{report}"
+    );
+    assert!(
+        report.contains("warning: lint/shadow.pluto:1: warning: ")
+            && report.contains("[global-shadow]"),
+        "{report}"
+    );
+}
+
+#[cfg(all(feature = "dynamic", target_os = "linux"))]
+#[test]
+fn test_dynamic() {
+    #[repr(C)]
+    struct DlInfo {
+        dli_fname: *const c_char,
+        dli_fbase: *mut c_void,
+        dli_sname: *const c_char,
+        dli_saddr: *mut c_void,
+    }
+
+    extern "C" {
+        fn dladdr(addr: *const c_void, info: *mut DlInfo) -> c_int;
+    }
+
+    // The Lua API must be resolved from the shared library, not linked into the test binary
+    let path = unsafe {
+        let mut info = std::mem::zeroed::<DlInfo>();
+        assert_ne!(dladdr(luaL_newstate as *const c_void, &mut info), 0);
+        std::ffi::CStr::from_ptr(info.dli_fname)
+            .to_string_lossy()
+            .into_owned()
+    };
+    assert!(path.ends_with("libpluto.so"), "{path}");
+    assert_eq!(run("return 1 + 1").as_deref(), Ok("2"));
+}
+
+#[test]
+fn test_include_dir() {
+    let include_dir = std::path::Path::new(env!("PLUTO_INCLUDE_DIR"));
+    for header in [
+        "lua.h",
+        "lauxlib.h",
+        "lualib.h",
+        "lua.hpp",
+        "pluto_config.h",
+    ] {
+        assert!(include_dir.join(header).is_file(), "{header}");
+    }
+    let luaconf = std::fs::read_to_string(include_dir.join("luaconf.h")).unwrap();
+    assert!(luaconf.contains("#include \"pluto_config.h\""));
+
+    let config = std::fs::read_to_string(include_dir.join("pluto_config.h")).unwrap();
+    if cfg!(feature = "memory-limit") {
+        assert!(
+            config.contains("#define PLUTO_MEMORY_LIMIT 16000000\n"),
+            "{config}"
+        );
+        assert!(
+            config.contains("#define PLUTO_MEMORY_LIMIT_PER_STATE\n"),
+            "{config}"
+        );
+    }
+    if cfg!(feature = "minimal-libs") {
+        assert!(config.contains("#define PLUTO_NO_XMLLIB\n"), "{config}");
+    }
+}
+
+/// Checks the `pluto_src::ffi` declarations against the prototypes in the Pluto headers.
+#[test]
+fn test_ffi_signatures() {
+    use std::collections::BTreeMap;
+
+    // Declarations that can't be expressed in Rust
+    const OMITTED: &[&str] = &["lua_pushvfstring"];
+
+    /// Converts a C parameter (or return type) into a Rust type, dropping the name.
+    fn rust_type(decl: &str) -> String {
+        let decl = decl.replace('*', " * ").replace("[]", " [] ");
+        let mut tokens: Vec<&str> = decl.split_whitespace().collect();
+        if tokens == ["..."] {
+            return "...".to_string();
+        }
+        // Whether each level (the base type, then the pointers) is const
+        let mut consts = vec![false];
+        let mut base = Vec::new();
+        let is_array = tokens.last() == Some(&"[]");
+        if is_array {
+            tokens.pop();
+        }
+        let name_len = match tokens.last() {
+            Some(&last) if tokens.len() > 1 && last != "*" && !is_c_keyword(last) => 1,
+            _ => 0,
+        };
+        for token in &tokens[..tokens.len() - name_len] {
+            match *token {
+                "const" => *consts.last_mut().unwrap() = true,
+                "*" => consts.push(false),
+                _ => base.push(*token),
+            }
+        }
+        if is_array {
+            consts.push(false);
+        }
+        let mut ty = match &base.join(" ")[..] {
+            "void" if consts.len() == 1 => "()",
+            "void" | "FILE" => "c_void",
+            "int" => "c_int",
+            "unsigned int" => "c_uint",
+            "char" => "c_char",
+            "size_t" => "usize",
+            other => other,
+        }
+        .to_string();
+        for pointee_const in &consts[..consts.len() - 1] {
+            let kind = if *pointee_const { "const" } else { "mut" };
+            ty = format!("*{kind} {ty}");
+        }
+        ty
+    }
+
+    fn is_c_keyword(token: &str) -> bool {
+        matches!(token, "int" | "char" | "void" | "size_t" | "double")
+    }
+
+    /// Collects `LUA_API`, `LUALIB_API` and `LUAMOD_API` prototypes as name -> (params, return).
+    fn c_prototypes(header: &str) -> BTreeMap<String, (Vec<String>, String)> {
+        let mut text = String::new();
+        for line in header.lines() {
+            if !line.trim_start().starts_with('#') {
+                text.push_str(line);
+                text.push(' ');
+            }
+        }
+        while let Some(start) = text.find("/*") {
+            let end = text[start..].find("*/").unwrap() + start + 2;
+            text.replace_range(start..end, " ");
+        }
+
+        let mut prototypes = BTreeMap::new();
+        for decl in text.split(';') {
+            // Declarations may follow the end of a block, without a `;`
+            let tokens: Vec<&str> = decl.split_whitespace().collect();
+            let is_api = |token: &&str| {
+                let api = token.trim_end_matches("_NORETURN");
+                matches!(api, "LUA_API" | "LUALIB_API" | "LUAMOD_API")
+            };
+            let Some(start) = tokens.iter().position(is_api) else {
+                continue;
+            };
+            let api = tokens[start];
+            let decl = tokens[start + 1..].join(" ");
+            let decl = decl.trim_end_matches("noexcept").trim_end();
+            let name_start = decl.find('(').unwrap();
+            let name_end = decl.find(')').unwrap();
+            let name = decl[name_start + 1..name_end].trim().to_string();
+            let ret = match api.ends_with("_NORETURN") {
+                true => "!".to_string(),
+                false => rust_type(&decl[..name_start]),
+            };
+            let params = decl[name_end + 1..].trim();
+            let params = &params[1..params.len() - 1];
+            let params = match params.trim() {
+                "void" => Vec::new(),
+                params => params.split(',').map(rust_type).collect(),
+            };
+            prototypes.insert(name, (params, ret));
+        }
+        prototypes
+    }
+
+    /// Collects the functions declared in `extern` blocks of the bindings.
+    fn rust_declarations(source: &str) -> BTreeMap<String, (Vec<String>, String)> {
+        let source: String = (source.lines())
+            .filter(|line| !line.trim_start().starts_with("//"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut declarations = BTreeMap::new();
+        for item in source.split("pub fn ").skip(1) {
+            let end = item.find([';', '{']).unwrap();
+            if item.as_bytes()[end] == b'{' {
+                // An inline function (macro)
+                continue;
+            }
+            let item = &item[..end];
+            let name_end = item.find('(').unwrap();
+            let params_end = item.rfind(')').unwrap();
+            let params = (item[name_end + 1..params_end].split(','))
+                .map(str::trim)
+                .filter(|param| !param.is_empty())
+                .map(|param| {
+                    let ty = param.split_once(':').map_or(param, |(_, ty)| ty);
+                    normalize(ty)
+                })
+                .collect();
+            let ret = match item[params_end + 1..].split_once("->") {
+                Some((_, ret)) => normalize(ret),
+                None => "()".to_string(),
+            };
+            declarations.insert(item[..name_end].trim().to_string(), (params, ret));
+        }
+        declarations
+    }
+
+    /// Nullable function pointers are `Option`s in Rust.
+    fn normalize(ty: &str) -> String {
+        let ty = ty.split_whitespace().collect::<Vec<_>>().join(" ");
+        match ty.strip_prefix("Option<") {
+            Some(ty) => ty.trim_end_matches('>').to_string(),
+            None => ty,
+        }
+    }
+
+    let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let mut prototypes = BTreeMap::new();
+    for header in ["lua.h", "lauxlib.h", "lualib.h"] {
+        let header = std::fs::read_to_string(root.join("pluto").join(header)).unwrap();
+        prototypes.extend(c_prototypes(&header));
+    }
+    let bindings = rust_declarations(&std::fs::read_to_string(root.join("src/ffi.rs")).unwrap());
+
+    assert!(prototypes.len() > 150, "{}", prototypes.len());
+    for (name, prototype) in &prototypes {
+        if OMITTED.contains(&&name[..]) {
+            assert!(!bindings.contains_key(name), "{name} should be omitted");
+            continue;
+        }
+        assert_eq!(bindings.get(name), Some(prototype), "{name}");
+    }
+    for name in bindings.keys() {
+        assert!(
+            prototypes.contains_key(name),
+            "{name} is not in the headers"
+        );
+    }
+}
+
+/// Stand-ins for the symbols of another Lua implementation linked into the same binary.
+#[cfg(feature = "symbol-prefix")]
+mod other_lua {
+    #[no_mangle]
+    pub extern "C" fn luaL_newstate() -> *mut std::ffi::c_void {
+        std::ptr::null_mut()
+    }
+
+    #[no_mangle]
+    #[allow(non_upper_case_globals)]
+    pub static luaP_opmodes: [u8; 1] = [0];
+
+    #[no_mangle]
+    #[allow(non_upper_case_globals)]
+    pub static lua_ident: [u8; 1] = [0];
+}
+
+#[cfg(feature = "symbol-prefix")]
+#[test]
+fn test_symbol_prefix() {
+    assert_eq!(run("return 'prefixed'").as_deref(), Ok("prefixed"));
+    assert!(other_lua::luaL_newstate().is_null());
+    assert_eq!(other_lua::luaP_opmodes, [0]);
+
+    let include_dir = std::path::Path::new(env!("PLUTO_INCLUDE_DIR"));
+    let config = std::fs::read_to_string(include_dir.join("pluto_config.h")).unwrap();
+    assert!(config.contains("#include \"pluto_rename.h\"\n"), "{config}");
+    let rename = std::fs::read_to_string(include_dir.join("pluto_rename.h")).unwrap();
+    for symbol in [
+        "lua_gettop",
+        "luaL_newstate",
+        "luaopen_json",
+        "luaP_opmodes",
+    ] {
+        assert!(
+            rename.contains(&format!("#define {symbol} pluto_{symbol}\n")),
+            "{symbol}"
+        );
+    }
+}